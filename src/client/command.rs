@@ -0,0 +1,652 @@
+use std::{collections::BTreeMap, ops::Range};
+
+use chrono::{DateTime, FixedOffset, NaiveDate};
+
+use super::{
+    parser::{is_astring_char, Capability},
+    sequence_set::SequenceSet,
+    utf7,
+};
+
+// renders a value like a mailbox name as astring, choosing the simplest form that can represent it
+// with literal_plus the literal is non-synchronizing as defined by
+// https://datatracker.ietf.org/doc/html/rfc7888, otherwise the caller has to wait for the
+// continuation request after the first line before sending the rest
+pub fn astring(value: &str, literal_plus: bool) -> String {
+    encode_astring(value, literal_plus, false)
+}
+
+// after ENABLE UTF8=ACCEPT quoted strings may contain UTF-8 as defined by
+// https://datatracker.ietf.org/doc/html/rfc6855#section-3
+fn encode_astring(value: &str, literal_plus: bool, utf8_accept: bool) -> String {
+    if !value.is_empty()
+        && value
+            .chars()
+            .all(|c| c.is_ascii() && !c.is_ascii_control() && is_astring_char(c))
+    {
+        value.to_string()
+    } else if value
+        .chars()
+        .all(|c| (c.is_ascii() || utf8_accept) && c != '\0' && c != '\r' && c != '\n')
+    {
+        let mut quoted = String::with_capacity(value.len() + 2);
+        quoted.push('"');
+        for c in value.chars() {
+            if c == '"' || c == '\\' {
+                quoted.push('\\');
+            }
+            quoted.push(c);
+        }
+        quoted.push('"');
+        quoted
+    } else {
+        let plus = if literal_plus { "+" } else { "" };
+        format!("{{{}{plus}}}\r\n{value}", value.len())
+    }
+}
+
+// mailbox names are sent in modified UTF-7, unless UTF8=ACCEPT is enabled
+pub fn mailbox(name: &str, literal_plus: bool, utf8_accept: bool) -> String {
+    if utf8_accept {
+        encode_astring(name, literal_plus, true)
+    } else {
+        astring(&utf7::encode(name), literal_plus)
+    }
+}
+
+// how flag changes since the last sync are found
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum ChangeTracking {
+    // by modseq, as defined by https://datatracker.ietf.org/doc/html/rfc7162
+    #[allow(dead_code)]
+    Condstore,
+    // plain IMAP4rev1 servers only allow fetching the flags of all mail again and comparing them
+    FullFetch,
+}
+
+impl ChangeTracking {
+    #[allow(dead_code)]
+    pub fn from_capabilities(capabilities: &[Capability]) -> Self {
+        // QRESYNC implies CONDSTORE
+        if capabilities.iter().any(|capability| {
+            matches!(capability, Capability::Custom(name)
+                if name.eq_ignore_ascii_case("CONDSTORE") || name.eq_ignore_ascii_case("QRESYNC"))
+        }) {
+            ChangeTracking::Condstore
+        } else {
+            ChangeTracking::FullFetch
+        }
+    }
+}
+
+// EXAMINE opens the mailbox read-only, so mirroring it neither clears \Recent nor can change flags
+pub fn select(
+    name: &str,
+    read_only: bool,
+    tracking: ChangeTracking,
+    literal_plus: bool,
+    utf8_accept: bool,
+) -> String {
+    let command = if read_only { "EXAMINE" } else { "SELECT" };
+    let name = mailbox(name, literal_plus, utf8_accept);
+    match tracking {
+        ChangeTracking::Condstore => format!("{command} {name} (CONDSTORE)"),
+        ChangeTracking::FullFetch => format!("{command} {name}"),
+    }
+}
+
+// https://datatracker.ietf.org/doc/html/rfc7162#section-3.2.5
+// the known uids scope VANISHED to mail that is actually tracked locally
+#[allow(dead_code)]
+pub fn qresync_select(
+    name: &str,
+    read_only: bool,
+    uid_validity: u32,
+    modseq: u64,
+    known_uids: &SequenceSet,
+    literal_plus: bool,
+    utf8_accept: bool,
+) -> String {
+    let command = if read_only { "EXAMINE" } else { "SELECT" };
+    let name = mailbox(name, literal_plus, utf8_accept);
+    if known_uids.is_empty() {
+        format!("{command} {name} (QRESYNC ({uid_validity} {modseq}))")
+    } else {
+        format!("{command} {name} (QRESYNC ({uid_validity} {modseq} {known_uids}))")
+    }
+}
+
+// lists all mailboxes with their status in one round trip instead of a STATUS for each, as
+// defined by https://datatracker.ietf.org/doc/html/rfc5819
+#[allow(dead_code)]
+pub fn list_status() -> String {
+    "LIST \"\" \"*\" RETURN (STATUS (MESSAGES UIDNEXT UIDVALIDITY HIGHESTMODSEQ))".to_string()
+}
+
+// summarizes a mailbox without selecting it
+#[allow(dead_code)]
+pub fn status(
+    name: &str,
+    tracking: ChangeTracking,
+    literal_plus: bool,
+    utf8_accept: bool,
+) -> String {
+    let name = mailbox(name, literal_plus, utf8_accept);
+    match tracking {
+        ChangeTracking::Condstore => {
+            format!("STATUS {name} (MESSAGES UIDNEXT UIDVALIDITY HIGHESTMODSEQ UNSEEN)")
+        }
+        ChangeTracking::FullFetch => format!("STATUS {name} (MESSAGES UIDNEXT UIDVALIDITY UNSEEN)"),
+    }
+}
+
+#[allow(dead_code)]
+pub fn supports_list_status(capabilities: &[Capability]) -> bool {
+    capabilities.iter().any(|capability| {
+        matches!(capability, Capability::Custom(name) if name.eq_ignore_ascii_case("LIST-STATUS"))
+    })
+}
+
+// non-synchronizing literals, as defined by https://datatracker.ietf.org/doc/html/rfc7888
+pub fn supports_literal_plus(capabilities: &[Capability]) -> bool {
+    capabilities.iter().any(|capability| {
+        matches!(capability, Capability::Custom(name) if name.eq_ignore_ascii_case("LITERAL+"))
+    })
+}
+
+pub fn supports_unselect(capabilities: &[Capability]) -> bool {
+    capabilities.iter().any(|capability| {
+        matches!(capability, Capability::Custom(name) if name.eq_ignore_ascii_case("UNSELECT"))
+    })
+}
+
+// closes the selected mailbox without expunging mail marked \Deleted, unlike CLOSE
+// as defined by https://datatracker.ietf.org/doc/html/rfc3691, otherwise a failing EXAMINE
+// leaves no mailbox selected either
+pub fn unselect(capabilities: &[Capability]) -> String {
+    if supports_unselect(capabilities) {
+        "UNSELECT".to_string()
+    } else {
+        "EXAMINE \"imapmaildir unselected\"".to_string()
+    }
+}
+
+// renders date-time as defined by https://datatracker.ietf.org/doc/html/rfc3501#section-9,
+// with the day padded by a space
+pub fn date_time(date_time: &DateTime<FixedOffset>) -> String {
+    format!("\"{}\"", date_time.format("%e-%b-%Y %H:%M:%S %z"))
+}
+
+// everything up to the literal announcing the message of the given size, which has to be sent
+// after it
+// passing the original date keeps the server from stamping uploaded mail with the current time
+pub fn append(
+    name: &str,
+    flags: &[&str],
+    internal_date: Option<&DateTime<FixedOffset>>,
+    size: usize,
+    literal_plus: bool,
+    utf8_accept: bool,
+) -> String {
+    let mut command = format!(
+        "APPEND {} ({})",
+        mailbox(name, literal_plus, utf8_accept),
+        flags.join(" ")
+    );
+    if let Some(internal_date) = internal_date {
+        command.push(' ');
+        command.push_str(&date_time(internal_date));
+    }
+    let plus = if literal_plus { "+" } else { "" };
+    command.push_str(&format!(" {{{size}{plus}}}"));
+    command
+}
+
+#[allow(dead_code)]
+pub fn enable(capabilities: &[&str]) -> String {
+    format!("ENABLE {}", capabilities.join(" "))
+}
+
+pub fn login(user: &str, password: &str, literal_plus: bool) -> String {
+    format!(
+        "LOGIN {} {}",
+        astring(user, literal_plus),
+        astring(password, literal_plus)
+    )
+}
+
+// with CONDSTORE enabled MODSEQ keeps the highest modseq accurate when only flags changed
+// servers without it reject MODSEQ as unknown attribute
+pub fn fetch_metadata(sequence_set: &SequenceSet, tracking: ChangeTracking) -> String {
+    match tracking {
+        ChangeTracking::Condstore => format!("UID FETCH {sequence_set} (UID FLAGS MODSEQ)"),
+        ChangeTracking::FullFetch => format!("UID FETCH {sequence_set} (UID FLAGS)"),
+    }
+}
+
+// only the flags of mail changed since the last sync, as defined by
+// https://datatracker.ietf.org/doc/html/rfc7162#section-3.1.4, the cheapest way to catch up when
+// nothing but flags changed
+// servers include MODSEQ on their own
+#[allow(dead_code)]
+pub fn fetch_flags_changed_since(sequence_set: &SequenceSet, modseq: u64) -> String {
+    format!("UID FETCH {sequence_set} (UID FLAGS) (CHANGEDSINCE {modseq})")
+}
+
+// UID and FLAGS are always fetched, as mail can't be stored without them
+// BODY.PEEK[] instead of RFC822, which implicitly sets \Seen on all fetched mail
+#[allow(dead_code)]
+pub fn fetch_mail(sequence_set: &SequenceSet, tracking: ChangeTracking) -> String {
+    match tracking {
+        ChangeTracking::Condstore => {
+            format!("UID FETCH {sequence_set} (UID FLAGS MODSEQ BODY.PEEK[])")
+        }
+        ChangeTracking::FullFetch => format!("UID FETCH {sequence_set} (UID FLAGS BODY.PEEK[])"),
+    }
+}
+
+// the MIME parts of each mail, to find the part numbers uid_fetch_part expects
+pub fn fetch_structure(sequence_set: &SequenceSet) -> String {
+    format!("UID FETCH {sequence_set} (UID BODYSTRUCTURE)")
+}
+
+// a single part of a mail as numbered in its BODYSTRUCTURE, or the whole mail for an empty part,
+// optionally only a range of its octets
+pub fn uid_fetch_part(uid: u32, part: &[u32], range: Option<Range<u32>>) -> String {
+    let part: Vec<String> = part.iter().map(u32::to_string).collect();
+    let partial = match range {
+        Some(range) => format!("<{}.{}>", range.start, range.len()),
+        None => String::new(),
+    };
+    format!("UID FETCH {uid} BODY.PEEK[{}]{partial}", part.join("."))
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum FlagChange {
+    Add,
+    Remove,
+}
+
+// flags changed on the same uids share one STORE, and commands are ordered by their first uid and
+// then their flags, so the same changes always produce the same commands
+pub fn uid_store(change: FlagChange, changed_flags: &[(&str, &[u32])]) -> Vec<String> {
+    let mut stores: BTreeMap<(u32, String), Vec<&str>> = BTreeMap::new();
+    for (flag, uids) in changed_flags {
+        let Some(first) = uids.iter().min() else {
+            continue;
+        };
+        let sequence_set = SequenceSet::from_numbers(uids.iter().copied());
+        stores
+            .entry((*first, sequence_set.to_string()))
+            .or_default()
+            .push(flag);
+    }
+    let sign = match change {
+        FlagChange::Add => '+',
+        FlagChange::Remove => '-',
+    };
+    stores
+        .into_iter()
+        .map(|((_, sequence_set), mut flags)| {
+            flags.sort_unstable();
+            flags.dedup();
+            format!(
+                "UID STORE {sequence_set} {sign}FLAGS.SILENT ({})",
+                flags.join(" ")
+            )
+        })
+        .collect()
+}
+
+#[allow(dead_code)]
+pub enum SearchKey {
+    Unseen,
+    From(String),
+    Since(NaiveDate),
+    Larger(u32),
+    // e.g. Message-ID to find an appended mail when the server doesn't report its uid
+    Header(String, String),
+    // passed through verbatim for keys not modelled here
+    Raw(String),
+}
+
+impl SearchKey {
+    fn render(&self, literal_plus: bool) -> String {
+        match self {
+            SearchKey::Unseen => "UNSEEN".to_string(),
+            SearchKey::From(from) => format!("FROM {}", astring(from, literal_plus)),
+            SearchKey::Since(date) => format!("SINCE {}", date.format("%-d-%b-%Y")),
+            SearchKey::Larger(size) => format!("LARGER {size}"),
+            SearchKey::Header(field, value) => format!(
+                "HEADER {} {}",
+                astring(field, literal_plus),
+                astring(value, literal_plus)
+            ),
+            SearchKey::Raw(key) => key.clone(),
+        }
+    }
+}
+
+#[allow(dead_code)]
+pub fn uid_search(keys: &[SearchKey], literal_plus: bool) -> String {
+    let keys: Vec<String> = keys.iter().map(|key| key.render(literal_plus)).collect();
+    format!("UID SEARCH {}", keys.join(" "))
+}
+
+// defined by https://datatracker.ietf.org/doc/html/rfc5256#section-3
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ThreadAlgorithm {
+    OrderedSubject,
+    References,
+}
+
+impl ThreadAlgorithm {
+    pub fn name(self) -> &'static str {
+        match self {
+            ThreadAlgorithm::OrderedSubject => "ORDEREDSUBJECT",
+            ThreadAlgorithm::References => "REFERENCES",
+        }
+    }
+}
+
+pub fn supports_thread(capabilities: &[Capability], algorithm: ThreadAlgorithm) -> bool {
+    capabilities.iter().any(|capability| {
+        matches!(capability, Capability::Custom(name) if name
+        .split_once('=')
+        .is_some_and(|(thread, supported)| {
+            thread.eq_ignore_ascii_case("THREAD")
+                && supported.eq_ignore_ascii_case(algorithm.name())
+        }))
+    })
+}
+
+// threads all mail without search keys
+pub fn uid_thread(algorithm: ThreadAlgorithm, keys: &[SearchKey], literal_plus: bool) -> String {
+    let keys: Vec<String> = keys.iter().map(|key| key.render(literal_plus)).collect();
+    let keys = if keys.is_empty() {
+        "ALL".to_string()
+    } else {
+        keys.join(" ")
+    };
+    format!("UID THREAD {} UTF-8 {keys}", algorithm.name())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keep_plain_mailbox_names_as_atoms() {
+        assert_eq!(astring("INBOX", false), "INBOX");
+        assert_eq!(astring("Archive/2024", false), "Archive/2024");
+    }
+
+    #[test]
+    fn quote_gmail_mailbox_names() {
+        assert_eq!(astring("[Gmail]/All Mail", false), "\"[Gmail]/All Mail\"");
+        assert_eq!(astring("[Gmail]/Sent Mail", false), "\"[Gmail]/Sent Mail\"");
+        assert_eq!(astring("say \"hi\"", false), "\"say \\\"hi\\\"\"");
+        assert_eq!(astring("", false), "\"\"");
+    }
+
+    #[test]
+    fn send_unquotable_names_as_literal() {
+        assert_eq!(astring("Entwürfe", false), "{9}\r\nEntwürfe");
+        assert_eq!(astring("Entwürfe", true), "{9+}\r\nEntwürfe");
+    }
+
+    #[test]
+    fn encode_unicode_mailbox_names() {
+        assert_eq!(mailbox("Entwürfe", false, false), "Entw&APw-rfe");
+        assert_eq!(
+            mailbox("Gelöschte Elemente", false, false),
+            "\"Gel&APY-schte Elemente\""
+        );
+    }
+
+    #[test]
+    fn quote_utf8_mailbox_names_when_accepted() {
+        assert_eq!(mailbox("Entwürfe", false, true), "\"Entwürfe\"");
+        assert_eq!(enable(&["UTF8=ACCEPT"]), "ENABLE UTF8=ACCEPT");
+    }
+
+    #[test]
+    fn examine_read_only_mailboxes() {
+        assert_eq!(
+            select("INBOX", false, ChangeTracking::FullFetch, false, false),
+            "SELECT INBOX"
+        );
+        assert_eq!(
+            select(
+                "[Gmail]/All Mail",
+                true,
+                ChangeTracking::Condstore,
+                false,
+                false
+            ),
+            "EXAMINE \"[Gmail]/All Mail\" (CONDSTORE)"
+        );
+    }
+
+    #[test]
+    fn resynchronize_known_uids() {
+        assert_eq!(
+            qresync_select(
+                "INBOX",
+                false,
+                67890007,
+                20050715194045000,
+                &SequenceSet::from_numbers([41, 42, 43, 44, 100]),
+                false,
+                false
+            ),
+            "SELECT INBOX (QRESYNC (67890007 20050715194045000 41:44,100))"
+        );
+        assert_eq!(
+            qresync_select(
+                "INBOX",
+                true,
+                67890007,
+                1,
+                &SequenceSet::from_numbers([]),
+                false,
+                false
+            ),
+            "EXAMINE INBOX (QRESYNC (67890007 1))"
+        );
+    }
+
+    #[test]
+    fn request_status_without_selecting() {
+        assert_eq!(
+            status("Sent Items", ChangeTracking::Condstore, false, false),
+            "STATUS \"Sent Items\" (MESSAGES UIDNEXT UIDVALIDITY HIGHESTMODSEQ UNSEEN)"
+        );
+        assert_eq!(
+            status("INBOX", ChangeTracking::FullFetch, false, false),
+            "STATUS INBOX (MESSAGES UIDNEXT UIDVALIDITY UNSEEN)"
+        );
+    }
+
+    #[test]
+    fn list_with_status_when_supported() {
+        assert_eq!(
+            list_status(),
+            "LIST \"\" \"*\" RETURN (STATUS (MESSAGES UIDNEXT UIDVALIDITY HIGHESTMODSEQ))"
+        );
+        assert!(supports_list_status(&[Capability::Custom("LIST-STATUS")]));
+        assert!(!supports_list_status(&[Capability::Custom(
+            "LIST-EXTENDED"
+        )]));
+    }
+
+    #[test]
+    fn append_with_original_date() {
+        let date = DateTime::parse_from_rfc2822("Mon, 7 Feb 1994 21:52:25 -0800").unwrap();
+        assert_eq!(date_time(&date), "\" 7-Feb-1994 21:52:25 -0800\"");
+        assert_eq!(
+            append("Sent", &["\\Seen"], Some(&date), 310, true, false),
+            "APPEND Sent (\\Seen) \" 7-Feb-1994 21:52:25 -0800\" {310+}"
+        );
+        assert_eq!(
+            append("Drafts", &[], None, 12, false, false),
+            "APPEND Drafts () {12}"
+        );
+    }
+
+    #[test]
+    fn quote_login_credentials() {
+        assert_eq!(
+            login("me@example.org", "pa ss\"word\\", false),
+            "LOGIN me@example.org \"pa ss\\\"word\\\\\""
+        );
+        assert_eq!(login("me", "grüße", true), "LOGIN me {7+}\r\ngrüße");
+    }
+
+    #[test]
+    fn fetch_metadata_of_all_mail() {
+        assert_eq!(
+            fetch_metadata(&SequenceSet::all(), ChangeTracking::Condstore),
+            "UID FETCH 1:* (UID FLAGS MODSEQ)"
+        );
+    }
+
+    #[test]
+    fn fetch_only_changed_flags() {
+        let fetch = fetch_flags_changed_since(&SequenceSet::all(), 90060115205545359);
+        assert_eq!(
+            fetch,
+            "UID FETCH 1:* (UID FLAGS) (CHANGEDSINCE 90060115205545359)"
+        );
+        assert!(!fetch.contains("RFC822") && !fetch.contains("BODY"));
+    }
+
+    #[test]
+    fn fetch_range_of_single_part() {
+        assert_eq!(
+            uid_fetch_part(7, &[2, 1], Some(1024..3072)),
+            "UID FETCH 7 BODY.PEEK[2.1]<1024.2048>"
+        );
+        assert_eq!(uid_fetch_part(7, &[3], None), "UID FETCH 7 BODY.PEEK[3]");
+    }
+
+    #[test]
+    fn fetch_structure_of_several_mails() {
+        assert_eq!(
+            fetch_structure(&SequenceSet::from_numbers([3, 4, 9])),
+            "UID FETCH 3:4,9 (UID BODYSTRUCTURE)"
+        );
+    }
+
+    #[test]
+    fn fetch_mail_without_marking_it_seen() {
+        let fetch = fetch_mail(&SequenceSet::all(), ChangeTracking::FullFetch);
+        assert!(fetch.contains("BODY.PEEK[]"));
+        assert!(!fetch.contains("RFC822"));
+    }
+
+    #[test]
+    fn fall_back_to_fetching_all_flags_without_condstore() {
+        let tracking = ChangeTracking::from_capabilities(&[Capability::Custom("IDLE")]);
+        assert_eq!(tracking, ChangeTracking::FullFetch);
+        assert_eq!(
+            fetch_metadata(&SequenceSet::all(), tracking),
+            "UID FETCH 1:* (UID FLAGS)"
+        );
+        assert_eq!(
+            ChangeTracking::from_capabilities(&[Capability::Custom("condstore")]),
+            ChangeTracking::Condstore
+        );
+    }
+
+    #[test]
+    fn combine_flag_changes_of_the_same_uids() {
+        let changed_flags: [(&str, &[u32]); 4] = [
+            ("\\Seen", &[7, 5, 6]),
+            ("$Junk", &[2]),
+            ("\\Flagged", &[5, 6, 7]),
+            ("\\Answered", &[]),
+        ];
+        assert_eq!(
+            uid_store(FlagChange::Add, &changed_flags),
+            vec![
+                "UID STORE 2 +FLAGS.SILENT ($Junk)",
+                "UID STORE 5:7 +FLAGS.SILENT (\\Flagged \\Seen)"
+            ]
+        );
+        assert_eq!(
+            uid_store(FlagChange::Remove, &changed_flags[..1]),
+            vec!["UID STORE 5:7 -FLAGS.SILENT (\\Seen)"]
+        );
+    }
+
+    #[test]
+    fn search_by_message_id() {
+        assert_eq!(
+            uid_search(
+                &[SearchKey::Header(
+                    "Message-ID".to_string(),
+                    "<1234@example.org>".to_string()
+                )],
+                false
+            ),
+            "UID SEARCH HEADER Message-ID <1234@example.org>"
+        );
+    }
+
+    #[test]
+    fn render_search_keys() {
+        let keys = [
+            SearchKey::Unseen,
+            SearchKey::From("Jane Doe".to_string()),
+            SearchKey::Since(NaiveDate::from_ymd_opt(1994, 2, 1).unwrap()),
+            SearchKey::Larger(1024),
+            SearchKey::Raw("NOT DELETED".to_string()),
+        ];
+        assert_eq!(
+            uid_search(&keys, false),
+            "UID SEARCH UNSEEN FROM \"Jane Doe\" SINCE 1-Feb-1994 LARGER 1024 NOT DELETED"
+        );
+    }
+
+    #[test]
+    fn thread_by_references() {
+        assert!(supports_thread(
+            &[
+                Capability::Custom("THREAD=ORDEREDSUBJECT"),
+                Capability::Custom("THREAD=REFERENCES")
+            ],
+            ThreadAlgorithm::References
+        ));
+        assert!(!supports_thread(
+            &[Capability::Custom("THREAD=ORDEREDSUBJECT")],
+            ThreadAlgorithm::References
+        ));
+        assert_eq!(
+            uid_thread(ThreadAlgorithm::References, &[], false),
+            "UID THREAD REFERENCES UTF-8 ALL"
+        );
+        assert_eq!(
+            uid_thread(ThreadAlgorithm::OrderedSubject, &[SearchKey::Unseen], false),
+            "UID THREAD ORDEREDSUBJECT UTF-8 UNSEEN"
+        );
+    }
+
+    #[test]
+    fn detect_literal_plus_in_any_case() {
+        assert!(supports_literal_plus(&[Capability::Custom("literal+")]));
+        assert!(!supports_literal_plus(&[Capability::Custom("LITERAL-")]));
+    }
+
+    #[test]
+    fn unselect_without_expunge() {
+        assert_eq!(
+            unselect(&[Capability::Custom("IDLE"), Capability::Custom("UNSELECT")]),
+            "UNSELECT"
+        );
+        assert_eq!(
+            unselect(&[Capability::Custom("IDLE")]),
+            "EXAMINE \"imapmaildir unselected\""
+        );
+    }
+}
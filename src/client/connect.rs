@@ -0,0 +1,147 @@
+use std::{
+    io,
+    net::{IpAddr, SocketAddr},
+    time::Duration,
+};
+
+use tokio::{
+    net::{lookup_host, TcpSocket, TcpStream},
+    task::JoinSet,
+    time::timeout,
+};
+
+// how long an attempt may stall before the next address is tried alongside it
+// https://datatracker.ietf.org/doc/html/rfc8305#section-5
+const HEAD_START: Duration = Duration::from_millis(250);
+
+// races the resolved addresses happy eyeballs style, so a broken IPv6 route doesn't stall the
+// IPv4 fallback, optionally egressing from bind_address, in which case only addresses of the
+// same family are usable
+pub async fn connect(host: &str, port: u16, bind_address: Option<IpAddr>) -> TcpStream {
+    let addresses: Vec<SocketAddr> = (lookup_host((host, port)).await)
+        .expect("server address should be resolvable")
+        .filter(|address| {
+            bind_address.is_none_or(|bind_address| bind_address.is_ipv4() == address.is_ipv4())
+        })
+        .collect();
+    assert!(
+        !addresses.is_empty(),
+        "server should have an address of the same family as bind_address"
+    );
+
+    let mut pending = interleave_families(addresses).into_iter();
+    let mut attempts = JoinSet::new();
+    let mut last_error = None;
+    loop {
+        if let Some(address) = pending.next() {
+            attempts.spawn(attempt(address, bind_address));
+        } else if attempts.is_empty() {
+            break;
+        }
+        let finished = if pending.len() > 0 {
+            match timeout(HEAD_START, attempts.join_next()).await {
+                Ok(finished) => finished,
+                Err(_) => continue,
+            }
+        } else {
+            attempts.join_next().await
+        };
+        match finished {
+            Some(Ok(Ok(stream))) => return stream,
+            Some(Ok(Err(error))) => last_error = Some(error),
+            Some(Err(error)) => panic!("connection attempt should not panic: {error}"),
+            None => {}
+        }
+    }
+    panic!("connection to server should succeed: {last_error:?}");
+}
+
+async fn attempt(address: SocketAddr, bind_address: Option<IpAddr>) -> io::Result<TcpStream> {
+    let socket = if address.is_ipv4() {
+        TcpSocket::new_v4()
+    } else {
+        TcpSocket::new_v6()
+    }?;
+    if let Some(bind_address) = bind_address {
+        socket.bind(SocketAddr::new(bind_address, 0))?;
+    }
+    socket.connect(address).await
+}
+
+// alternates between address families, starting with the resolver's preferred one
+fn interleave_families(addresses: Vec<SocketAddr>) -> Vec<SocketAddr> {
+    let Some(preferred_v4) = addresses.first().map(SocketAddr::is_ipv4) else {
+        return addresses;
+    };
+    let (mut preferred, mut other): (Vec<_>, Vec<_>) = addresses
+        .into_iter()
+        .partition(|address| address.is_ipv4() == preferred_v4);
+    let mut interleaved = Vec::with_capacity(preferred.len() + other.len());
+    preferred.reverse();
+    other.reverse();
+    while !preferred.is_empty() || !other.is_empty() {
+        interleaved.extend(preferred.pop());
+        interleaved.extend(other.pop());
+    }
+    interleaved
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::net::TcpListener;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn connect_from_bind_address() {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("listener should bind");
+        let port = listener
+            .local_addr()
+            .expect("listener should have an address")
+            .port();
+        let bind_address: IpAddr = "127.0.0.1".parse().unwrap();
+
+        let stream = connect("127.0.0.1", port, Some(bind_address)).await;
+        let (_, peer) = listener.accept().await.expect("client should connect");
+
+        assert_eq!(peer.ip(), bind_address);
+        assert_eq!(
+            stream.local_addr().expect("stream should be bound").ip(),
+            bind_address
+        );
+    }
+
+    #[tokio::test]
+    #[should_panic(expected = "same family as bind_address")]
+    async fn reject_bind_address_of_other_family() {
+        connect("127.0.0.1", 993, Some("::1".parse().unwrap())).await;
+    }
+
+    #[test]
+    fn alternate_address_families() {
+        let addresses: Vec<SocketAddr> = [
+            "[2001:db8::1]:993",
+            "[2001:db8::2]:993",
+            "[2001:db8::3]:993",
+            "192.0.2.1:993",
+        ]
+        .iter()
+        .map(|address| address.parse().unwrap())
+        .collect();
+        let interleaved: Vec<String> = interleave_families(addresses)
+            .iter()
+            .map(SocketAddr::to_string)
+            .collect();
+        assert_eq!(
+            interleaved,
+            [
+                "[2001:db8::1]:993",
+                "192.0.2.1:993",
+                "[2001:db8::2]:993",
+                "[2001:db8::3]:993"
+            ]
+        );
+    }
+}
@@ -1,54 +1,1759 @@
+mod command;
+mod connect;
 mod parser;
+mod proxy;
+mod sasl;
+#[cfg(test)]
+pub(crate) mod scripted_server;
+mod sequence_set;
+mod tag;
+mod transport;
+mod utf7;
 
-use parser::parse_greeting;
-use tokio::{
-    io::{split, AsyncBufReadExt, AsyncWriteExt, BufReader, BufWriter, ReadHalf, WriteHalf},
-    net::TcpStream,
+use std::{
+    fmt::{self, Display, Formatter},
+    future::Future,
+    io,
+    ops::Range,
+    time::Duration,
 };
-use tokio_native_tls::{native_tls, TlsConnector, TlsStream};
 
-use crate::config::Config;
+use chrono::{DateTime, FixedOffset};
+use command::ChangeTracking;
+pub use command::{FlagChange, ThreadAlgorithm};
+use parser::{
+    parse_append_uid, parse_body_section, parse_body_structure, parse_capabilities,
+    parse_completion, parse_greeting, parse_mail_metadata, parse_thread, parse_uid_next,
+    Capability, GreetingStatus, Status,
+};
+pub use parser::{MessagePart, MessageStructure, Thread};
+pub use sequence_set::SequenceSet;
+use tag::TagGenerator;
+use tokio::{
+    io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt},
+    process::Child,
+    time::timeout,
+};
+use transport::{Reader, Transport, Writer};
 
-type Reader = BufReader<ReadHalf<TlsStream<TcpStream>>>;
-type Writer = BufWriter<WriteHalf<TlsStream<TcpStream>>>;
+use crate::config::{Config, Timeouts};
 
 pub struct Client {
     reader: Reader,
     writer: Writer,
+    authenticated: bool,
+    // killed when the client is dropped
+    #[allow(dead_code)]
+    tunnel: Option<Child>,
+    tags: TagGenerator,
+    // response advertising the capabilities, parsed again whenever needed, as they borrow from it
+    capabilities: String,
+    timeouts: Timeouts,
+    // set once a command timed out, as its responses may still arrive and be taken for those of
+    // the next command
+    poisoned: bool,
+    // as announced on selecting the current mailbox, if the server did
+    uid_next: Option<u32>,
 }
 
 impl Client {
     pub async fn new(config: &Config) -> Self {
-        let tls = native_tls::TlsConnector::new().expect("native tls should be available");
-        let tls = TlsConnector::from(tls);
-        let stream = (TcpStream::connect((config.host(), config.port)).await)
-            .expect("connection to server should succeed");
-        let stream =
-            (tls.connect(config.host(), stream).await).expect("upgrading to tls should succeed");
+        Self::with_transport(Transport::open(config).await, config.timeouts().clone()).await
+    }
 
-        let (reader, writer) = split(stream);
-        let mut reader = BufReader::new(reader);
-        let mut writer = BufWriter::new(writer);
+    pub(crate) async fn with_transport(transport: Transport, timeouts: Timeouts) -> Self {
+        let Transport {
+            mut reader,
+            mut writer,
+            tunnel,
+        } = transport;
 
         let mut res = String::new();
         (reader.read_line(&mut res).await).expect("greeting should be readable");
-        dbg!(&res);
         let greeting_response = parse_greeting(&res).expect("greeting should be parseable");
-        dbg!(greeting_response);
-        get_capabilities(&mut reader, &mut writer).await;
+        if let Some(alert) = greeting_response.alert {
+            eprintln!("[ALERT] {alert}");
+        }
+        if greeting_response.status == GreetingStatus::Bye {
+            panic!("server rejected connection: {}", greeting_response.text);
+        }
+        // a pre-authenticated connection must not LOGIN again
+        let authenticated = greeting_response.status == GreetingStatus::PreAuth;
+        let mut tags = TagGenerator::new();
+        let capabilities = get_capabilities(&mut reader, &mut writer, &tags.next(), timeouts.login)
+            .await
+            .expect("server should advertise its capabilities in time");
+
+        Client {
+            reader,
+            writer,
+            authenticated,
+            tunnel,
+            tags,
+            capabilities,
+            timeouts,
+            poisoned: false,
+            uid_next: None,
+        }
+    }
+
+    fn capabilities(&self) -> Vec<Capability<'_>> {
+        parse_capabilities(&self.capabilities).unwrap_or_default()
+    }
+
+    // uids are assigned in ascending order, so SequenceSet::below of it covers all mail that
+    // existed when the mailbox was selected
+    pub fn uid_next(&self) -> Option<u32> {
+        self.uid_next
+    }
+
+    // a mailbox the server refuses to open only fails syncing that mailbox, not the whole run
+    pub async fn select(&mut self, mailbox: &str, read_only: bool) -> Result<(), SelectError> {
+        if self.poisoned {
+            return Err(SelectError {
+                mailbox: mailbox.to_string(),
+                text: POISONED.to_string(),
+            });
+        }
+        self.uid_next = None;
+        let tag = self.tags.next();
+        // neither CONDSTORE, LITERAL+ nor UTF8=ACCEPT is used yet
+        let select = command::select(mailbox, read_only, ChangeTracking::FullFetch, false, false);
+        (self
+            .writer
+            .write_all(format!("{tag} {select}\r\n").as_bytes()))
+        .await
+        .expect("writing select command to buffer should succeed");
+        (self.writer.flush())
+            .await
+            .expect("sending select command should succeed");
+        let (untagged, completion) =
+            match read_responses(&mut self.reader, &tag, Some(self.timeouts.select)).await {
+                Ok(responses) => responses,
+                Err(ReadError::TimedOut) => {
+                    self.poisoned = true;
+                    return Err(SelectError {
+                        mailbox: mailbox.to_string(),
+                        text: TIMED_OUT.to_string(),
+                    });
+                }
+                Err(ReadError::ForeignCompletion) => {
+                    panic!("server should only complete the SELECT command")
+                }
+            };
+        match parse_completion(&completion) {
+            Ok(completion) if completion.status == Status::Ok => {
+                self.uid_next = untagged
+                    .iter()
+                    .find_map(|response| parse_uid_next(response).ok());
+                Ok(())
+            }
+            Ok(completion) => Err(SelectError {
+                mailbox: mailbox.to_string(),
+                text: completion.text.to_string(),
+            }),
+            Err(()) => Err(SelectError {
+                mailbox: mailbox.to_string(),
+                text: "connection closed".to_string(),
+            }),
+        }
+    }
+
+    // returns the uid the server assigned, if it supports UIDPLUS
+    pub async fn append(
+        &mut self,
+        mailbox: &str,
+        flags: &[&str],
+        internal_date: Option<&DateTime<FixedOffset>>,
+        message: &[u8],
+    ) -> Result<Option<u32>, AppendError> {
+        let refused = |text: &str| AppendError {
+            mailbox: mailbox.to_string(),
+            text: text.to_string(),
+        };
+        if self.poisoned {
+            return Err(refused(POISONED));
+        }
+        let tag = self.tags.next();
+        // LITERAL+ isn't used yet, so the literal has to wait for the server's go-ahead
+        let append = command::append(mailbox, flags, internal_date, message.len(), false, false);
+        (self
+            .writer
+            .write_all(format!("{tag} {append}\r\n").as_bytes()))
+        .await
+        .expect("writing append command to buffer should succeed");
+        (self.writer.flush())
+            .await
+            .expect("sending append command should succeed");
+        // untagged responses, e.g. EXISTS for mail delivered meanwhile, may precede the go-ahead
+        let mut res = loop {
+            let res = match read_response(&mut self.reader, Some(self.timeouts.append)).await {
+                Ok(res) => res.line,
+                Err(_) => {
+                    self.poisoned = true;
+                    return Err(refused(TIMED_OUT));
+                }
+            };
+            if !res.starts_with("* ") {
+                break res;
+            }
+        };
+        if !res.starts_with('+') && !res.starts_with(&format!("{tag} ")) {
+            // the server may still be waiting for the literal, so nothing else can be sent
+            self.poisoned = true;
+            return Err(refused(if res.is_empty() {
+                "connection closed"
+            } else {
+                "unexpected response"
+            }));
+        }
+        if res.starts_with('+') {
+            (self.writer.write_all(message).await)
+                .expect("writing message to buffer should succeed");
+            (self.writer.write_all(b"\r\n").await)
+                .expect("writing end of append command to buffer should succeed");
+            (self.writer.flush())
+                .await
+                .expect("sending message should succeed");
+            res = match read_until_tagged(&mut self.reader, &tag, Some(self.timeouts.append)).await
+            {
+                Ok(completion) => completion,
+                Err(ReadError::TimedOut) => {
+                    self.poisoned = true;
+                    return Err(refused(TIMED_OUT));
+                }
+                Err(ReadError::ForeignCompletion) => {
+                    panic!("server should only complete the APPEND command")
+                }
+            };
+        }
+        match parse_completion(&res) {
+            Ok(completion) if completion.status == Status::Ok => Ok(parse_append_uid(&res)
+                .expect("completion should be parseable")
+                .and_then(|append_uid| append_uid.uids.first().map(|uids| *uids.start()))),
+            Ok(completion) => Err(refused(completion.text)),
+            Err(()) => Err(refused("connection closed")),
+        }
+    }
+
+    // downloads a single part of mail in the selected mailbox, e.g. an attachment of mail that is
+    // kept without it, or only a range of its octets to resume an interrupted download
+    pub async fn fetch_part(
+        &mut self,
+        uid: u32,
+        part: &[u32],
+        range: Option<Range<u32>>,
+    ) -> Result<Vec<u8>, FetchError> {
+        if self.poisoned {
+            return Err(FetchError {
+                text: POISONED.to_string(),
+            });
+        }
+        let tag = self.tags.next();
+        let fetch = command::uid_fetch_part(uid, part, range);
+        (self
+            .writer
+            .write_all(format!("{tag} {fetch}\r\n").as_bytes()))
+        .await
+        .expect("writing fetch command to buffer should succeed");
+        (self.writer.flush())
+            .await
+            .expect("sending fetch command should succeed");
+        let (untagged, completion) =
+            match read_raw_responses(&mut self.reader, &tag, Some(self.timeouts.fetch)).await {
+                Ok(responses) => responses,
+                Err(ReadError::TimedOut) => {
+                    self.poisoned = true;
+                    return Err(FetchError {
+                        text: TIMED_OUT.to_string(),
+                    });
+                }
+                Err(ReadError::ForeignCompletion) => {
+                    panic!("server should only complete the FETCH command")
+                }
+            };
+        match parse_completion(&completion) {
+            Ok(completion) if completion.status == Status::Ok => {}
+            Ok(completion) => {
+                return Err(FetchError {
+                    text: completion.text.to_string(),
+                })
+            }
+            Err(()) => {
+                return Err(FetchError {
+                    text: "connection closed".to_string(),
+                })
+            }
+        }
+        // the server may send unsolicited FETCH responses, e.g. for flags changed meanwhile
+        untagged
+            .iter()
+            .find_map(|response| {
+                let body = parse_body_section(&response.line).ok()?;
+                (body.uid == Some(uid))
+                    .then(|| response.octets(body.data.unwrap_or_default()).to_vec())
+            })
+            .ok_or_else(|| FetchError {
+                text: format!("no such mail or part: {uid}"),
+            })
+    }
+
+    // lists the MIME parts of each mail in the selected mailbox, to pick one for fetch_part
+    pub async fn fetch_structure(
+        &mut self,
+        sequence_set: &SequenceSet,
+    ) -> Result<Vec<MessageStructure>, FetchError> {
+        if self.poisoned {
+            return Err(FetchError {
+                text: POISONED.to_string(),
+            });
+        }
+        let tag = self.tags.next();
+        let fetch = command::fetch_structure(sequence_set);
+        (self
+            .writer
+            .write_all(format!("{tag} {fetch}\r\n").as_bytes()))
+        .await
+        .expect("writing fetch command to buffer should succeed");
+        (self.writer.flush())
+            .await
+            .expect("sending fetch command should succeed");
+        let (untagged, completion) =
+            match read_responses(&mut self.reader, &tag, Some(self.timeouts.fetch)).await {
+                Ok(responses) => responses,
+                Err(ReadError::TimedOut) => {
+                    self.poisoned = true;
+                    return Err(FetchError {
+                        text: TIMED_OUT.to_string(),
+                    });
+                }
+                Err(ReadError::ForeignCompletion) => {
+                    panic!("server should only complete the FETCH command")
+                }
+            };
+        match parse_completion(&completion) {
+            Ok(completion) if completion.status == Status::Ok => {}
+            Ok(completion) => {
+                return Err(FetchError {
+                    text: completion.text.to_string(),
+                })
+            }
+            Err(()) => {
+                return Err(FetchError {
+                    text: "connection closed".to_string(),
+                })
+            }
+        }
+        // unsolicited FETCH responses, e.g. for flags changed meanwhile, carry no parts
+        Ok(untagged
+            .iter()
+            .filter_map(|response| parse_body_structure(response).ok())
+            .filter(|structure| !structure.parts.is_empty())
+            .collect())
+    }
+
+    // the flags of each mail in the selected mailbox as letters of the maildir info suffix, with
+    // keywords mapped as in Flag::maildir_letter and flags without a letter left out
+    // ignored flags are left out as well, so that they are never pulled
+    pub async fn fetch_flags(
+        &mut self,
+        sequence_set: &SequenceSet,
+        keyword_letter: impl Fn(&str) -> Option<char>,
+        ignores_flag: impl Fn(&str) -> bool,
+    ) -> Result<Vec<(u32, String)>, FetchError> {
+        if self.poisoned {
+            return Err(FetchError {
+                text: POISONED.to_string(),
+            });
+        }
+        let tag = self.tags.next();
+        // CONDSTORE isn't enabled yet
+        let fetch = command::fetch_metadata(sequence_set, ChangeTracking::FullFetch);
+        (self
+            .writer
+            .write_all(format!("{tag} {fetch}\r\n").as_bytes()))
+        .await
+        .expect("writing fetch command to buffer should succeed");
+        (self.writer.flush())
+            .await
+            .expect("sending fetch command should succeed");
+        let (untagged, completion) =
+            match read_responses(&mut self.reader, &tag, Some(self.timeouts.fetch)).await {
+                Ok(responses) => responses,
+                Err(ReadError::TimedOut) => {
+                    self.poisoned = true;
+                    return Err(FetchError {
+                        text: TIMED_OUT.to_string(),
+                    });
+                }
+                Err(ReadError::ForeignCompletion) => {
+                    panic!("server should only complete the FETCH command")
+                }
+            };
+        match parse_completion(&completion) {
+            Ok(completion) if completion.status == Status::Ok => {}
+            Ok(completion) => {
+                return Err(FetchError {
+                    text: completion.text.to_string(),
+                })
+            }
+            Err(()) => {
+                return Err(FetchError {
+                    text: "connection closed".to_string(),
+                })
+            }
+        }
+        Ok(untagged
+            .iter()
+            .filter_map(|response| parse_mail_metadata(response).ok())
+            .filter(|metadata| {
+                let Some(uid_next) = self.uid_next else {
+                    return true;
+                };
+                // a broken server, the mail is left out rather than stored under a uid that might
+                // be assigned to other mail later on
+                match metadata.check_plausible(uid_next) {
+                    Ok(()) => true,
+                    Err(error) => {
+                        eprintln!("{error}");
+                        false
+                    }
+                }
+            })
+            .map(|metadata| {
+                // maildir expects the letters in ASCII order
+                let mut letters: Vec<char> = metadata
+                    .flags
+                    .iter()
+                    .filter(|flag| !ignores_flag(&flag.to_string()))
+                    .filter_map(|flag| flag.maildir_letter(&keyword_letter))
+                    .collect();
+                letters.sort_unstable();
+                letters.dedup();
+                (metadata.uid, letters.into_iter().collect())
+            })
+            .collect())
+    }
+
+    // groups all mail of the selected mailbox into conversations
+    pub async fn thread(&mut self, algorithm: ThreadAlgorithm) -> Result<Vec<Thread>, ThreadError> {
+        if self.poisoned {
+            return Err(ThreadError {
+                text: POISONED.to_string(),
+            });
+        }
+        if !command::supports_thread(&self.capabilities(), algorithm) {
+            return Err(ThreadError {
+                text: format!("THREAD={} is not supported", algorithm.name()),
+            });
+        }
+        let tag = self.tags.next();
+        let thread = command::uid_thread(algorithm, &[], false);
+        (self
+            .writer
+            .write_all(format!("{tag} {thread}\r\n").as_bytes()))
+        .await
+        .expect("writing thread command to buffer should succeed");
+        (self.writer.flush())
+            .await
+            .expect("sending thread command should succeed");
+        // like FETCH, THREAD may take a while on large mailboxes
+        let (untagged, completion) =
+            match read_responses(&mut self.reader, &tag, Some(self.timeouts.fetch)).await {
+                Ok(responses) => responses,
+                Err(ReadError::TimedOut) => {
+                    self.poisoned = true;
+                    return Err(ThreadError {
+                        text: TIMED_OUT.to_string(),
+                    });
+                }
+                Err(ReadError::ForeignCompletion) => {
+                    panic!("server should only complete the THREAD command")
+                }
+            };
+        match parse_completion(&completion) {
+            Ok(completion) if completion.status == Status::Ok => Ok(untagged
+                .iter()
+                .filter_map(|response| parse_thread(response).ok())
+                .flatten()
+                .collect()),
+            Ok(completion) => Err(ThreadError {
+                text: completion.text.to_string(),
+            }),
+            Err(()) => Err(ThreadError {
+                text: "connection closed".to_string(),
+            }),
+        }
+    }
+
+    // servers commonly advertise more capabilities once authenticated, e.g. QRESYNC or MOVE, so they
+    // are updated, either from the completion or by asking again
+    pub async fn login(&mut self, user: &str, password: &str) -> Result<(), LoginError> {
+        // a challenge-response mechanism keeps the password off the wire, PLAIN is no better than LOGIN
+        let exchange = sasl::Mechanism::strongest(&self.capabilities())
+            .and_then(|mechanism| sasl::Exchange::new(mechanism, user, password));
+        self.login_with(user, password, exchange).await
+    }
+
+    async fn login_with(
+        &mut self,
+        user: &str,
+        password: &str,
+        exchange: Option<sasl::Exchange>,
+    ) -> Result<(), LoginError> {
+        if self.authenticated {
+            return Ok(());
+        }
+        if self.poisoned {
+            return Err(LoginError {
+                text: POISONED.to_string(),
+            });
+        }
+        let (untagged, completion) = match exchange {
+            Some(exchange) => self.authenticate(exchange).await?,
+            None => self.send_login(user, password).await?,
+        };
+        match parse_completion(&completion) {
+            Ok(completion) if completion.status == Status::Ok => {}
+            Ok(completion) => {
+                return Err(LoginError {
+                    text: completion.text.to_string(),
+                })
+            }
+            Err(()) => {
+                return Err(LoginError {
+                    text: "connection closed".to_string(),
+                })
+            }
+        }
+        self.authenticated = true;
+        self.capabilities = if let Some(advertised) = untagged
+            .into_iter()
+            .find(|response| parse_capabilities(response).is_ok())
+        {
+            advertised
+        } else if parse_capabilities(&completion).is_ok() {
+            completion
+        } else {
+            let tag = self.tags.next();
+            match get_capabilities(
+                &mut self.reader,
+                &mut self.writer,
+                &tag,
+                self.timeouts.login,
+            )
+            .await
+            {
+                Ok(capabilities) => capabilities,
+                Err(ReadError::TimedOut) => return Err(self.login_timed_out()),
+                Err(ReadError::ForeignCompletion) => {
+                    panic!("server should only complete the CAPABILITY command")
+                }
+            }
+        };
+        Ok(())
+    }
+
+    // returns the untagged responses and the completion of LOGIN
+    async fn send_login(
+        &mut self,
+        user: &str,
+        password: &str,
+    ) -> Result<(Vec<String>, String), LoginError> {
+        if !sasl::plaintext_allowed(&self.capabilities()) {
+            return Err(LoginError {
+                text: "server disabled LOGIN".to_string(),
+            });
+        }
+        let tag = self.tags.next();
+        let literal_plus = command::supports_literal_plus(&self.capabilities());
+        let login = format!("{tag} {}\r\n", command::login(user, password, literal_plus));
+        // without LITERAL+ each literal has to wait for the server's go-ahead
+        let mut completion = String::new();
+        for part in login.split_inclusive("}\r\n") {
+            (self.writer.write_all(part.as_bytes()))
+                .await
+                .expect("writing login command to buffer should succeed");
+            (self.writer.flush())
+                .await
+                .expect("sending login command should succeed");
+            if !literal_plus && part.ends_with("}\r\n") {
+                match timeout(self.timeouts.login, self.reader.read_line(&mut completion)).await {
+                    Ok(read) => read.expect("response should be readable"),
+                    Err(_) => return Err(self.login_timed_out()),
+                };
+                if !completion.starts_with('+') {
+                    return Ok((Vec::new(), completion));
+                }
+                completion.clear();
+            }
+        }
+        match read_responses(&mut self.reader, &tag, Some(self.timeouts.login)).await {
+            Ok(responses) => Ok(responses),
+            Err(ReadError::TimedOut) => Err(self.login_timed_out()),
+            Err(ReadError::ForeignCompletion) => {
+                panic!("server should only complete the LOGIN command")
+            }
+        }
+    }
+
+    // answers the server's challenges until it completes AUTHENTICATE, https://datatracker.ietf.org/doc/html/rfc3501#section-6.2.2
+    // returns the untagged responses and the completion
+    async fn authenticate(
+        &mut self,
+        mut exchange: sasl::Exchange,
+    ) -> Result<(Vec<String>, String), LoginError> {
+        let tag = self.tags.next();
+        (self.writer.write_all(
+            format!("{tag} AUTHENTICATE {}\r\n", exchange.mechanism().name()).as_bytes(),
+        ))
+        .await
+        .expect("writing authenticate command to buffer should succeed");
+        (self.writer.flush())
+            .await
+            .expect("sending authenticate command should succeed");
+        let mut untagged = Vec::new();
+        let mut cancelled = false;
+        let completion = loop {
+            let mut res = String::new();
+            let read = match timeout(self.timeouts.login, self.reader.read_line(&mut res)).await {
+                Ok(read) => read.expect("response should be readable"),
+                Err(_) => return Err(self.login_timed_out()),
+            };
+            if read == 0 {
+                break res;
+            }
+            if let Some(challenge) = res.strip_prefix('+') {
+                // "*" cancels the exchange, which the server then fails
+                let response = exchange.respond(challenge.trim()).unwrap_or_else(|()| {
+                    cancelled = true;
+                    "*".to_string()
+                });
+                (self.writer.write_all(format!("{response}\r\n").as_bytes()))
+                    .await
+                    .expect("writing authenticate response to buffer should succeed");
+                (self.writer.flush())
+                    .await
+                    .expect("sending authenticate response should succeed");
+                continue;
+            }
+            match res.split_once(' ') {
+                Some(("*", _)) => untagged.push(res),
+                Some((received, _)) if received == tag => break res,
+                Some(_) => panic!("server should only complete the AUTHENTICATE command"),
+                None => {}
+            }
+        };
+        // a server accepting without proving that it knows the password may be an impostor
+        let accepted =
+            parse_completion(&completion).is_ok_and(|completion| completion.status == Status::Ok);
+        if cancelled || (accepted && !exchange.verified()) {
+            return Err(LoginError {
+                text: format!("server failed the {} exchange", exchange.mechanism().name()),
+            });
+        }
+        Ok((untagged, completion))
+    }
+
+    fn login_timed_out(&mut self) -> LoginError {
+        self.poisoned = true;
+        LoginError {
+            text: TIMED_OUT.to_string(),
+        }
+    }
+
+    // leaves the selected mailbox, e.g. before selecting the next one, without expunging it
+    // the fallback without UNSELECT is expected to fail, so the completion is not checked
+    pub async fn unselect(&mut self) -> Result<(), UnselectError> {
+        if self.poisoned {
+            return Err(UnselectError {
+                text: POISONED.to_string(),
+            });
+        }
+        let tag = self.tags.next();
+        let unselect = command::unselect(&self.capabilities());
+        (self
+            .writer
+            .write_all(format!("{tag} {unselect}\r\n").as_bytes()))
+        .await
+        .expect("writing unselect command to buffer should succeed");
+        (self.writer.flush())
+            .await
+            .expect("sending unselect command should succeed");
+        match read_until_tagged(&mut self.reader, &tag, Some(self.timeouts.select)).await {
+            Ok(completion) if completion.is_empty() => Err(UnselectError {
+                text: "connection closed".to_string(),
+            }),
+            Ok(_) => {
+                self.uid_next = None;
+                Ok(())
+            }
+            Err(ReadError::TimedOut) => {
+                self.poisoned = true;
+                Err(UnselectError {
+                    text: TIMED_OUT.to_string(),
+                })
+            }
+            Err(ReadError::ForeignCompletion) => {
+                panic!("server should only complete the UNSELECT command")
+            }
+        }
+    }
+
+    // adds or removes flags of mail in the selected mailbox, one STORE per set of changed uids
+    pub async fn store(
+        &mut self,
+        change: FlagChange,
+        changed_flags: &[(&str, &[u32])],
+    ) -> Result<(), StoreError> {
+        for store in command::uid_store(change, changed_flags) {
+            if self.poisoned {
+                return Err(StoreError {
+                    text: POISONED.to_string(),
+                });
+            }
+            let tag = self.tags.next();
+            (self
+                .writer
+                .write_all(format!("{tag} {store}\r\n").as_bytes()))
+            .await
+            .expect("writing store command to buffer should succeed");
+            (self.writer.flush())
+                .await
+                .expect("sending store command should succeed");
+            let completion =
+                match read_until_tagged(&mut self.reader, &tag, Some(self.timeouts.store)).await {
+                    Ok(completion) => completion,
+                    Err(ReadError::TimedOut) => {
+                        self.poisoned = true;
+                        return Err(StoreError {
+                            text: TIMED_OUT.to_string(),
+                        });
+                    }
+                    Err(ReadError::ForeignCompletion) => {
+                        panic!("server should only complete the STORE command")
+                    }
+                };
+            match parse_completion(&completion) {
+                Ok(completion) if completion.status == Status::Ok => {}
+                Ok(completion) => {
+                    return Err(StoreError {
+                        text: completion.text.to_string(),
+                    })
+                }
+                Err(()) => {
+                    return Err(StoreError {
+                        text: "connection closed".to_string(),
+                    })
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // best effort, a server that does not answer in time should not delay exiting
+    pub async fn logout(mut self) {
+        let tag = self.tags.next();
+        let logout = async {
+            (self
+                .writer
+                .write_all(format!("{tag} LOGOUT\r\n").as_bytes()))
+            .await
+            .expect("writing logout command to buffer should succeed");
+            (self.writer.flush())
+                .await
+                .expect("sending logout command should succeed");
+            // the server may still be busy with the command that timed out, so the confirmation is
+            // not waited for
+            if self.poisoned {
+                return Ok(String::new());
+            }
+            read_until_tagged(&mut self.reader, &tag, Some(Duration::from_secs(5))).await
+        };
+        match logout.await {
+            Ok(_) => {}
+            Err(ReadError::ForeignCompletion) => {
+                eprintln!("server completed a command other than LOGOUT")
+            }
+            Err(ReadError::TimedOut) => eprintln!("server did not confirm LOGOUT in time"),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct SelectError {
+    pub mailbox: String,
+    pub text: String,
+}
+
+impl Display for SelectError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "server refused to open {}: {}", self.mailbox, self.text)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct AppendError {
+    pub mailbox: String,
+    pub text: String,
+}
+
+impl Display for AppendError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "server refused to append to {}: {}",
+            self.mailbox, self.text
+        )
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct LoginError {
+    pub text: String,
+}
+
+impl Display for LoginError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "server refused login: {}", self.text)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct ThreadError {
+    pub text: String,
+}
+
+impl Display for ThreadError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "server refused to thread mail: {}", self.text)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct ImplausibleError {
+    pub text: String,
+}
+
+impl Display for ImplausibleError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "server sent implausible data: {}", self.text)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct FetchError {
+    pub text: String,
+}
+
+impl Display for FetchError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "server refused to fetch mail: {}", self.text)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct UnselectError {
+    pub text: String,
+}
+
+impl Display for UnselectError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "server did not close the mailbox: {}", self.text)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct StoreError {
+    pub text: String,
+}
 
-        Client { reader, writer }
+impl Display for StoreError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "server refused to change flags: {}", self.text)
     }
 }
 
-async fn get_capabilities(reader: &mut Reader, writer: &mut Writer) {
-    (writer.write_all(b"abcd CAPABILITY\r\n"))
+const TIMED_OUT: &str = "no response in time";
+const POISONED: &str = "connection unusable after an earlier command timed out";
+
+#[derive(Debug, PartialEq)]
+enum ReadError {
+    // none but this one command is outstanding
+    ForeignCompletion,
+    TimedOut,
+}
+
+// reads untagged responses until the tagged completion of the command and returns it, or an
+// empty line if the connection was closed
+// the timeout applies to each response, so that a slow but steady stream of them is not aborted
+async fn read_until_tagged(
+    reader: &mut Reader,
+    tag: &str,
+    idle: Option<Duration>,
+) -> Result<String, ReadError> {
+    read_responses(reader, tag, idle)
+        .await
+        .map(|(_, completion)| completion)
+}
+
+// like read_until_tagged, but keeps the untagged responses
+async fn read_responses(
+    reader: &mut Reader,
+    tag: &str,
+    idle: Option<Duration>,
+) -> Result<(Vec<String>, String), ReadError> {
+    let (untagged, completion) = read_raw_responses(reader, tag, idle).await?;
+    Ok((
+        untagged.into_iter().map(|response| response.line).collect(),
+        completion,
+    ))
+}
+
+// like read_responses, but keeps the octets of literals that aren't text
+async fn read_raw_responses(
+    reader: &mut Reader,
+    tag: &str,
+    idle: Option<Duration>,
+) -> Result<(Vec<Response>, String), ReadError> {
+    let mut untagged = Vec::new();
+    loop {
+        let res = read_response(reader, idle).await?;
+        if res.line.is_empty() {
+            return Ok((untagged, res.line));
+        }
+        match res.line.split_once(' ') {
+            Some(("*", _)) => untagged.push(res),
+            Some(("+", _)) | None => {}
+            Some((received, _)) if received == tag => return Ok((untagged, res.line)),
+            Some(_) => return Err(ReadError::ForeignCompletion),
+        }
+    }
+}
+
+// the parser works on text, so literals that aren't are replaced by as many placeholder octets in
+// the line and kept aside as received
+struct Response {
+    line: String,
+    // offset in line and octets of each literal
+    literals: Vec<(usize, Vec<u8>)>,
+}
+
+impl Response {
+    // the octets of a literal parsed from the line, or of any other part of it as is
+    fn octets<'a>(&'a self, part: &'a str) -> &'a [u8] {
+        let offset = (part.as_ptr() as usize).wrapping_sub(self.line.as_ptr() as usize);
+        self.literals
+            .iter()
+            .find(|(literal_offset, _)| *literal_offset == offset)
+            .map_or(part.as_bytes(), |(_, literal)| literal)
+    }
+}
+
+// reads one response including its literals, whose content may span lines, or returns an empty
+// line if the connection was closed
+async fn read_response(reader: &mut Reader, idle: Option<Duration>) -> Result<Response, ReadError> {
+    let mut res = Response {
+        line: String::new(),
+        literals: Vec::new(),
+    };
+    loop {
+        let start = res.line.len();
+        within(idle, reader.read_line(&mut res.line)).await?;
+        let Some(length) = literal_length(&res.line[start..]) else {
+            return Ok(res);
+        };
+        let mut literal = vec![0; length];
+        within(idle, reader.read_exact(&mut literal)).await?;
+        match std::str::from_utf8(&literal) {
+            Ok(text) if !text.contains('\0') => res.line.push_str(text),
+            _ => {
+                res.literals.push((res.line.len(), literal));
+                res.line.push_str(&"?".repeat(length));
+            }
+        }
+    }
+}
+
+// a line ending in {<length>} announces that many octets of literal
+fn literal_length(line: &str) -> Option<usize> {
+    let (_, length) = line.strip_suffix("}\r\n")?.rsplit_once('{')?;
+    length.parse().ok()
+}
+
+async fn within<T>(
+    idle: Option<Duration>,
+    read: impl Future<Output = io::Result<T>>,
+) -> Result<T, ReadError> {
+    let read = match idle {
+        Some(idle) => timeout(idle, read).await.map_err(|_| ReadError::TimedOut)?,
+        None => read.await,
+    };
+    Ok(read.expect("response should be readable"))
+}
+
+// returns the untagged CAPABILITY response, or an empty line if the server sent none
+async fn get_capabilities(
+    reader: &mut Reader,
+    writer: &mut Writer,
+    tag: &str,
+    idle: Duration,
+) -> Result<String, ReadError> {
+    (writer.write_all(format!("{tag} CAPABILITY\r\n").as_bytes()))
         .await
         .expect("writing capability command to buffer should succeed");
     (writer.flush())
         .await
         .expect("sending capability command should succeed");
-    let mut res = String::new();
-    (reader.read_line(&mut res).await).expect("greeting should be readable");
-    dbg!(&res);
+    let (untagged, _) = read_responses(reader, tag, Some(idle)).await?;
+    Ok(untagged
+        .into_iter()
+        .find(|response| parse_capabilities(response).is_ok())
+        .unwrap_or_default())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use scripted_server::{serve, Step, DOVECOT_GREETING, GMAIL_CAPABILITY, GMAIL_GREETING};
+
+    #[tokio::test]
+    async fn connect_and_logout_from_dovecot() {
+        let (transport, server) = serve(vec![
+            Step {
+                command: None,
+                responses: DOVECOT_GREETING,
+            },
+            Step {
+                command: Some("a0001 CAPABILITY"),
+                responses: "* CAPABILITY IMAP4rev1 LITERAL+ AUTH=PLAIN\r\na0001 OK Capability completed.\r\n",
+            },
+            Step {
+                command: Some("a0002 LOGOUT"),
+                responses: "* BYE Logging out\r\na0002 OK Logout completed.\r\n",
+            },
+        ]);
+        let client = Client::with_transport(transport, Timeouts::default()).await;
+        assert!(!client.authenticated);
+        client.logout().await;
+        server.await.expect("client should follow the script");
+    }
+
+    #[tokio::test]
+    async fn report_mailbox_the_server_refuses_to_select() {
+        let (transport, server) = serve(vec![
+            Step {
+                command: None,
+                responses: DOVECOT_GREETING,
+            },
+            Step {
+                command: Some("a0001 CAPABILITY"),
+                responses: "a0001 OK Capability completed.\r\n",
+            },
+            Step {
+                command: Some("a0002 SELECT Missing"),
+                responses: "a0002 NO [NONEXISTENT] Mailbox doesn't exist: Missing\r\n",
+            },
+            Step {
+                command: Some("a0003 EXAMINE INBOX"),
+                responses: "* 3 EXISTS\r\na0003 OK [READ-ONLY] Examine completed.\r\n",
+            },
+        ]);
+        let mut client = Client::with_transport(transport, Timeouts::default()).await;
+        assert_eq!(
+            client.select("Missing", false).await,
+            Err(SelectError {
+                mailbox: "Missing".to_string(),
+                text: "Mailbox doesn't exist: Missing".to_string()
+            })
+        );
+        assert_eq!(client.select("INBOX", true).await, Ok(()));
+        server.await.expect("client should follow the script");
+    }
+
+    #[tokio::test]
+    async fn append_after_continuation() {
+        let (transport, server) = serve(vec![
+            Step {
+                command: None,
+                responses: DOVECOT_GREETING,
+            },
+            Step {
+                command: Some("a0001 CAPABILITY"),
+                responses: "a0001 OK Capability completed.\r\n",
+            },
+            Step {
+                command: Some("a0002 APPEND Archive (\\Seen) {20}"),
+                responses: "+ OK\r\n",
+            },
+            Step {
+                command: Some("Subject: one"),
+                responses: "",
+            },
+            Step {
+                command: Some(""),
+                responses: "",
+            },
+            Step {
+                command: Some("body"),
+                responses: "a0002 OK [APPENDUID 38505 3955] Append completed.\r\n",
+            },
+            Step {
+                command: Some("a0003 APPEND Missing () {4}"),
+                responses: "a0003 NO [TRYCREATE] Mailbox doesn't exist: Missing\r\n",
+            },
+        ]);
+        let mut client = Client::with_transport(transport, Timeouts::default()).await;
+        assert_eq!(
+            client
+                .append("Archive", &["\\Seen"], None, b"Subject: one\r\n\r\nbody")
+                .await,
+            Ok(Some(3955))
+        );
+        assert_eq!(
+            client.append("Missing", &[], None, b"body").await,
+            Err(AppendError {
+                mailbox: "Missing".to_string(),
+                text: "Mailbox doesn't exist: Missing".to_string()
+            })
+        );
+        server.await.expect("client should follow the script");
+    }
+
+    #[tokio::test]
+    async fn append_after_untagged_responses() {
+        let (transport, server) = serve(vec![
+            Step {
+                command: None,
+                responses: DOVECOT_GREETING,
+            },
+            Step {
+                command: Some("a0001 CAPABILITY"),
+                responses: "a0001 OK Capability completed.\r\n",
+            },
+            Step {
+                command: Some("a0002 APPEND Archive () {4}"),
+                responses: "* 3 EXISTS\r\n* OK [ALERT] Quota almost exceeded\r\n+ OK\r\n",
+            },
+            Step {
+                command: Some("body"),
+                responses: "a0002 OK Append completed.\r\n",
+            },
+            Step {
+                command: Some("a0003 APPEND Archive () {4}"),
+                responses: "a0002 OK Stray completion\r\n",
+            },
+        ]);
+        let mut client = Client::with_transport(transport, Timeouts::default()).await;
+        assert_eq!(client.append("Archive", &[], None, b"body").await, Ok(None));
+        assert_eq!(
+            client.append("Archive", &[], None, b"body").await,
+            Err(AppendError {
+                mailbox: "Archive".to_string(),
+                text: "unexpected response".to_string()
+            })
+        );
+        assert!(client.poisoned);
+        server.await.expect("client should follow the script");
+    }
+
+    #[tokio::test]
+    async fn thread_selected_mailbox() {
+        let (transport, server) = serve(vec![
+            Step {
+                command: None,
+                responses: DOVECOT_GREETING,
+            },
+            Step {
+                command: Some("a0001 CAPABILITY"),
+                responses:
+                    "* CAPABILITY IMAP4rev1 THREAD=REFERENCES\r\na0001 OK Capability completed.\r\n",
+            },
+            Step {
+                command: Some("a0002 UID THREAD REFERENCES UTF-8 ALL"),
+                responses: "* THREAD (2)(3 6)\r\na0002 OK Thread completed.\r\n",
+            },
+        ]);
+        let mut client = Client::with_transport(transport, Timeouts::default()).await;
+        assert_eq!(
+            client.thread(ThreadAlgorithm::References).await,
+            Ok(vec![
+                Thread {
+                    uid: Some(2),
+                    children: vec![]
+                },
+                Thread {
+                    uid: Some(3),
+                    children: vec![Thread {
+                        uid: Some(6),
+                        children: vec![]
+                    }]
+                },
+            ])
+        );
+        server.await.expect("client should follow the script");
+    }
+
+    #[tokio::test]
+    async fn unselect_if_advertised() {
+        let (transport, server) = serve(vec![
+            Step {
+                command: None,
+                responses: DOVECOT_GREETING,
+            },
+            Step {
+                command: Some("a0001 CAPABILITY"),
+                responses: "* CAPABILITY IMAP4rev1 UNSELECT\r\na0001 OK Capability completed.\r\n",
+            },
+            Step {
+                command: Some("a0002 UNSELECT"),
+                responses: "a0002 OK Unselect completed.\r\n",
+            },
+        ]);
+        let mut client = Client::with_transport(transport, Timeouts::default()).await;
+        assert_eq!(client.unselect().await, Ok(()));
+        server.await.expect("client should follow the script");
+    }
+
+    #[tokio::test]
+    async fn unselect_by_failing_examine() {
+        let (transport, server) = serve(vec![
+            Step {
+                command: None,
+                responses: DOVECOT_GREETING,
+            },
+            Step {
+                command: Some("a0001 CAPABILITY"),
+                responses: "* CAPABILITY IMAP4rev1\r\na0001 OK Capability completed.\r\n",
+            },
+            Step {
+                command: Some("a0002 EXAMINE \"imapmaildir unselected\""),
+                responses: "a0002 NO Mailbox doesn't exist\r\n",
+            },
+        ]);
+        let mut client = Client::with_transport(transport, Timeouts::default()).await;
+        assert_eq!(client.unselect().await, Ok(()));
+        server.await.expect("client should follow the script");
+    }
+
+    #[tokio::test]
+    async fn ask_for_capabilities_again_after_login() {
+        let (transport, server) = serve(vec![
+            Step {
+                command: None,
+                responses: DOVECOT_GREETING,
+            },
+            Step {
+                command: Some("a0001 CAPABILITY"),
+                responses:
+                    "* CAPABILITY IMAP4rev1 AUTH=PLAIN\r\na0001 OK Capability completed.\r\n",
+            },
+            Step {
+                command: Some("a0002 LOGIN me secret"),
+                responses: "a0002 OK Logged in\r\n",
+            },
+            Step {
+                command: Some("a0003 CAPABILITY"),
+                responses:
+                    "* CAPABILITY IMAP4rev1 QRESYNC UNSELECT\r\na0003 OK Capability completed.\r\n",
+            },
+            Step {
+                command: Some("a0004 UNSELECT"),
+                responses: "a0004 OK Unselect completed.\r\n",
+            },
+        ]);
+        let mut client = Client::with_transport(transport, Timeouts::default()).await;
+        assert_eq!(client.login("me", "secret").await, Ok(()));
+        assert!(client.authenticated);
+        assert!(client
+            .capabilities()
+            .contains(&Capability::Custom("QRESYNC")));
+        assert_eq!(client.unselect().await, Ok(()));
+        server.await.expect("client should follow the script");
+    }
+
+    #[tokio::test]
+    async fn take_capabilities_from_login_completion() {
+        let (transport, server) = serve(vec![
+            Step {
+                command: None,
+                responses: DOVECOT_GREETING,
+            },
+            Step {
+                command: Some("a0001 CAPABILITY"),
+                responses:
+                    "* CAPABILITY IMAP4rev1 AUTH=PLAIN\r\na0001 OK Capability completed.\r\n",
+            },
+            Step {
+                command: Some("a0002 LOGIN me {6}"),
+                responses: "+ OK\r\n",
+            },
+            Step {
+                command: Some("grüß"),
+                responses: "a0002 OK [CAPABILITY IMAP4rev1 UNSELECT] Logged in\r\n",
+            },
+            Step {
+                command: Some("a0003 UNSELECT"),
+                responses: "a0003 OK Unselect completed.\r\n",
+            },
+        ]);
+        let mut client = Client::with_transport(transport, Timeouts::default()).await;
+        assert_eq!(client.login("me", "grüß").await, Ok(()));
+        assert_eq!(client.unselect().await, Ok(()));
+        server.await.expect("client should follow the script");
+    }
+
+    #[tokio::test]
+    async fn authenticate_with_cram_md5() {
+        let (transport, server) = serve(vec![
+            Step {
+                command: None,
+                responses: DOVECOT_GREETING,
+            },
+            Step {
+                command: Some("a0001 CAPABILITY"),
+                responses: "* CAPABILITY IMAP4rev1 AUTH=PLAIN AUTH=CRAM-MD5\r\na0001 OK Capability completed.\r\n",
+            },
+            Step {
+                command: Some("a0002 AUTHENTICATE CRAM-MD5"),
+                // example from https://datatracker.ietf.org/doc/html/rfc2195#section-2
+                responses: "+ PDE4OTYuNjk3MTcwOTUyQHBvc3RvZmZpY2UucmVzdG9uLm1jaS5uZXQ+\r\n",
+            },
+            Step {
+                command: Some("dGltIGI5MTNhNjAyYzdlZGE3YTQ5NWI0ZTZlNzMzNGQzODkw"),
+                responses: "a0002 OK [CAPABILITY IMAP4rev1 UNSELECT] Logged in\r\n",
+            },
+        ]);
+        let mut client = Client::with_transport(transport, Timeouts::default()).await;
+        assert_eq!(client.login("tim", "tanstaaftanstaaf").await, Ok(()));
+        assert!(client
+            .capabilities()
+            .contains(&Capability::Custom("UNSELECT")));
+        server.await.expect("client should follow the script");
+    }
+
+    // example from https://datatracker.ietf.org/doc/html/rfc7677#section-3
+    fn scram_script(
+        server_final: &'static str,
+        client_last: &'static str,
+        completion: &'static str,
+    ) -> Vec<Step> {
+        vec![
+            Step {
+                command: None,
+                responses: DOVECOT_GREETING,
+            },
+            Step {
+                command: Some("a0001 CAPABILITY"),
+                responses: "* CAPABILITY IMAP4rev1 AUTH=SCRAM-SHA-256\r\na0001 OK Capability completed.\r\n",
+            },
+            Step {
+                command: Some("a0002 AUTHENTICATE SCRAM-SHA-256"),
+                responses: "+ \r\n",
+            },
+            Step {
+                command: Some("biwsbj11c2VyLHI9ck9wck5HZndFYmVSV2diTkVrcU8="),
+                responses: "+ cj1yT3ByTkdmd0ViZVJXZ2JORWtxTyVodllEcFdVYTJSYVRDQWZ1eEZJbGopaE5sRiRrMCxzPVcyMlphSjBTTlk3c29Fc1VFamI2Z1E9PSxpPTQwOTY=\r\n",
+            },
+            Step {
+                command: Some("Yz1iaXdzLHI9ck9wck5HZndFYmVSV2diTkVrcU8laHZZRHBXVWEyUmFUQ0FmdXhGSWxqKWhObEYkazAscD1kSHpiWmFwV0lrNGpVaE4rVXRlOXl0YWc5empmTUhnc3FtbWl6N0FuZFZRPQ=="),
+                responses: server_final,
+            },
+            Step {
+                command: Some(client_last),
+                responses: completion,
+            },
+        ]
+    }
+
+    fn scram_exchange() -> sasl::Exchange {
+        sasl::Exchange::ScramSha256 {
+            scram: sasl::ScramSha256::with_nonce("user", "pencil", "rOprNGfwEbeRWgbNEkqO".into()),
+            challenges: 0,
+        }
+    }
+
+    #[tokio::test]
+    async fn authenticate_with_scram_sha_256() {
+        let (transport, server) = serve(scram_script(
+            "+ dj02cnJpVFJCaTIzV3BSUi93dHVwK21NaFVaVW4vZEI1bkxUSlJzamw5NUc0PQ==\r\n",
+            "",
+            "a0002 OK [CAPABILITY IMAP4rev1] Logged in\r\n",
+        ));
+        let mut client = Client::with_transport(transport, Timeouts::default()).await;
+        assert_eq!(
+            client
+                .login_with("user", "pencil", Some(scram_exchange()))
+                .await,
+            Ok(())
+        );
+        server.await.expect("client should follow the script");
+    }
+
+    #[tokio::test]
+    async fn cancel_scram_sha_256_with_forged_server_signature() {
+        let (transport, server) = serve(scram_script(
+            "+ dj1BQUFBVFJCaTIzV3BSUi93dHVwK21NaFVaVW4vZEI1bkxUSlJzamw5NUc0PQ==\r\n",
+            // cancels the exchange
+            "*",
+            "a0002 BAD Authentication cancelled\r\n",
+        ));
+        let mut client = Client::with_transport(transport, Timeouts::default()).await;
+        assert_eq!(
+            client
+                .login_with("user", "pencil", Some(scram_exchange()))
+                .await,
+            Err(LoginError {
+                text: "server failed the SCRAM-SHA-256 exchange".to_string()
+            })
+        );
+        assert!(!client.authenticated);
+        server.await.expect("client should follow the script");
+    }
+
+    #[tokio::test]
+    async fn give_up_on_select_without_response() {
+        let (transport, server) = serve(vec![
+            Step {
+                command: None,
+                responses: DOVECOT_GREETING,
+            },
+            Step {
+                command: Some("a0001 CAPABILITY"),
+                responses: "a0001 OK Capability completed.\r\n",
+            },
+            Step {
+                command: Some("a0002 SELECT INBOX"),
+                responses: "* 3 EXISTS\r\n",
+            },
+            Step {
+                command: Some("a0003 LOGOUT"),
+                responses: "",
+            },
+        ]);
+        let mut client = Client::with_transport(
+            transport,
+            Timeouts {
+                select: Duration::from_millis(50),
+                ..Timeouts::default()
+            },
+        )
+        .await;
+        assert_eq!(
+            client.select("INBOX", false).await,
+            Err(SelectError {
+                mailbox: "INBOX".to_string(),
+                text: TIMED_OUT.to_string()
+            })
+        );
+        // the late completion of SELECT must not be taken for that of another command
+        assert_eq!(
+            client.unselect().await,
+            Err(UnselectError {
+                text: POISONED.to_string()
+            })
+        );
+        client.logout().await;
+        server.await.expect("client should follow the script");
+    }
+
+    #[tokio::test]
+    async fn store_flags_of_each_uid_set() {
+        let (transport, server) = serve(vec![
+            Step {
+                command: None,
+                responses: DOVECOT_GREETING,
+            },
+            Step {
+                command: Some("a0001 CAPABILITY"),
+                responses: "a0001 OK Capability completed.\r\n",
+            },
+            Step {
+                command: Some("a0002 UID STORE 2 +FLAGS.SILENT (\\Flagged)"),
+                responses: "a0002 OK Store completed.\r\n",
+            },
+            Step {
+                command: Some("a0003 UID STORE 5:7 +FLAGS.SILENT (\\Seen)"),
+                responses: "a0003 NO [CANNOT] Mailbox is read-only\r\n",
+            },
+        ]);
+        let mut client = Client::with_transport(transport, Timeouts::default()).await;
+        assert_eq!(
+            client
+                .store(
+                    FlagChange::Add,
+                    &[("\\Flagged", &[2]), ("\\Seen", &[5, 6, 7])]
+                )
+                .await,
+            Err(StoreError {
+                text: "Mailbox is read-only".to_string()
+            })
+        );
+        server.await.expect("client should follow the script");
+    }
+
+    #[tokio::test]
+    async fn fetch_range_of_attachment() {
+        let (transport, server) = serve(vec![
+            Step {
+                command: None,
+                responses: DOVECOT_GREETING,
+            },
+            Step {
+                command: Some("a0001 CAPABILITY"),
+                responses: "a0001 OK Capability completed.\r\n",
+            },
+            Step {
+                command: Some("a0002 UID FETCH 7 BODY.PEEK[2]<0.10>"),
+                responses: "* 3 FETCH (FLAGS (\\Seen) UID 5)\r\n* 1 FETCH (UID 7 BODY[2]<0> {10}\r\nSGVs\r\nbG8g)\r\na0002 OK Fetch completed.\r\n",
+            },
+            Step {
+                command: Some("a0003 UID FETCH 8 BODY.PEEK[2]"),
+                responses: "a0003 OK Fetch completed.\r\n",
+            },
+        ]);
+        let mut client = Client::with_transport(transport, Timeouts::default()).await;
+        assert_eq!(
+            client.fetch_part(7, &[2], Some(0..10)).await,
+            Ok(b"SGVs\r\nbG8g".to_vec())
+        );
+        assert_eq!(
+            client.fetch_part(8, &[2], None).await,
+            Err(FetchError {
+                text: "no such mail or part: 8".to_string()
+            })
+        );
+        server.await.expect("client should follow the script");
+    }
+
+    #[tokio::test]
+    async fn fetch_part_that_is_not_text() {
+        let (transport, server) = serve(vec![
+            Step {
+                command: None,
+                responses: DOVECOT_GREETING.as_bytes(),
+            },
+            Step {
+                command: Some("a0001 CAPABILITY"),
+                responses: b"a0001 OK Capability completed.\r\n",
+            },
+            Step {
+                command: Some("a0002 UID FETCH 7 BODY.PEEK[2]"),
+                responses: b"* 1 FETCH (UID 7 BODY[2] {5}\r\n\xff\x00\r\n\xfe)\r\na0002 OK Fetch completed.\r\n",
+            },
+        ]);
+        let mut client = Client::with_transport(transport, Timeouts::default()).await;
+        assert_eq!(
+            client.fetch_part(7, &[2], None).await,
+            Ok(b"\xff\x00\r\n\xfe".to_vec())
+        );
+        server.await.expect("client should follow the script");
+    }
+
+    #[tokio::test]
+    async fn list_parts_of_fetched_mails() {
+        let (transport, server) = serve(vec![
+            Step {
+                command: None,
+                responses: DOVECOT_GREETING,
+            },
+            Step {
+                command: Some("a0001 CAPABILITY"),
+                responses: "a0001 OK Capability completed.\r\n",
+            },
+            Step {
+                command: Some("a0002 UID FETCH 7:8 (UID BODYSTRUCTURE)"),
+                responses: "* 1 FETCH (UID 7 BODYSTRUCTURE (\"text\" \"plain\" (\"charset\" \"utf-8\") NIL NIL \"7bit\" 42 2 NIL NIL NIL NIL))\r\n* 3 FETCH (UID 5 FLAGS (\\Seen))\r\n* 2 FETCH (UID 8 BODYSTRUCTURE ((\"text\" \"plain\" (\"charset\" \"utf-8\") NIL NIL \"7bit\" 120 4 NIL NIL NIL NIL)(\"image\" \"png\" (\"name\" \"logo.png\") NIL NIL \"base64\" 4096 NIL NIL NIL NIL) \"mixed\" (\"boundary\" \"b1\") NIL NIL NIL))\r\na0002 OK Fetch completed.\r\n",
+            },
+        ]);
+        let mut client = Client::with_transport(transport, Timeouts::default()).await;
+        let structures = client
+            .fetch_structure(&SequenceSet::from_numbers([7, 8]))
+            .await
+            .expect("structure should be fetched");
+        let parts: Vec<_> = structures
+            .iter()
+            .flat_map(|structure| {
+                structure
+                    .parts
+                    .iter()
+                    .map(|part| (structure.uid, part.part.clone(), part.octets))
+            })
+            .collect();
+        assert_eq!(
+            parts,
+            [
+                (Some(7), vec![1], 42),
+                (Some(8), vec![1], 120),
+                (Some(8), vec![2], 4096)
+            ]
+        );
+        server.await.expect("client should follow the script");
+    }
+
+    #[tokio::test]
+    async fn fetch_flags_as_maildir_letters() {
+        let (transport, server) = serve(vec![
+            Step {
+                command: None,
+                responses: DOVECOT_GREETING,
+            },
+            Step {
+                command: Some("a0001 CAPABILITY"),
+                responses: "a0001 OK Capability completed.\r\n",
+            },
+            Step {
+                command: Some("a0002 UID FETCH 1:* (UID FLAGS)"),
+                responses: "* 1 FETCH (UID 3 FLAGS (\\Seen \\Answered $Label1 Junk))\r\n* 2 FETCH (UID 4 FLAGS (\\Recent))\r\na0002 OK Fetch completed.\r\n",
+            },
+        ]);
+        let mut client = Client::with_transport(transport, Timeouts::default()).await;
+        let keyword_letter = |keyword: &str| (keyword == "$Label1").then_some('a');
+        assert_eq!(
+            client
+                .fetch_flags(&SequenceSet::all(), keyword_letter, |_| false)
+                .await,
+            Ok(vec![(3, "RSa".to_string()), (4, String::new())])
+        );
+        server.await.expect("client should follow the script");
+    }
+
+    #[tokio::test]
+    async fn leave_out_ignored_flags() {
+        let (transport, server) = serve(vec![
+            Step {
+                command: None,
+                responses: DOVECOT_GREETING,
+            },
+            Step {
+                command: Some("a0001 CAPABILITY"),
+                responses: "a0001 OK Capability completed.\r\n",
+            },
+            Step {
+                command: Some("a0002 UID FETCH 1:* (UID FLAGS)"),
+                responses:
+                    "* 1 FETCH (UID 3 FLAGS (\\Seen \\Flagged))\r\na0002 OK Fetch completed.\r\n",
+            },
+        ]);
+        let mut client = Client::with_transport(transport, Timeouts::default()).await;
+        let ignores_flag = |flag: &str| flag.eq_ignore_ascii_case("\\seen");
+        assert_eq!(
+            client
+                .fetch_flags(&SequenceSet::all(), |_| None, ignores_flag)
+                .await,
+            Ok(vec![(3, "F".to_string())])
+        );
+        server.await.expect("client should follow the script");
+    }
+
+    #[tokio::test]
+    async fn leave_out_flags_of_uids_beyond_uid_next() {
+        let (transport, server) = serve(vec![
+            Step {
+                command: None,
+                responses: DOVECOT_GREETING,
+            },
+            Step {
+                command: Some("a0001 CAPABILITY"),
+                responses: "a0001 OK Capability completed.\r\n",
+            },
+            Step {
+                command: Some("a0002 SELECT INBOX"),
+                responses: "* 2 EXISTS\r\n* OK [UIDNEXT 5] Predicted next UID\r\na0002 OK [READ-WRITE] Select completed.\r\n",
+            },
+            Step {
+                command: Some("a0003 UID FETCH 1:4 (UID FLAGS)"),
+                responses: "* 1 FETCH (UID 3 FLAGS (\\Seen))\r\n* 2 FETCH (UID 7 FLAGS (\\Seen))\r\na0003 OK Fetch completed.\r\n",
+            },
+        ]);
+        let mut client = Client::with_transport(transport, Timeouts::default()).await;
+        assert_eq!(client.select("INBOX", false).await, Ok(()));
+        assert_eq!(client.uid_next(), Some(5));
+        assert_eq!(
+            client
+                .fetch_flags(&SequenceSet::below(5), |_| None, |_| false)
+                .await,
+            Ok(vec![(3, "S".to_string())])
+        );
+        server.await.expect("client should follow the script");
+    }
+
+    #[tokio::test]
+    async fn refuse_to_thread_without_capability() {
+        let (transport, server) = serve(vec![
+            Step {
+                command: None,
+                responses: DOVECOT_GREETING,
+            },
+            Step {
+                command: Some("a0001 CAPABILITY"),
+                responses: "* CAPABILITY IMAP4rev1 THREAD=ORDEREDSUBJECT\r\na0001 OK Capability completed.\r\n",
+            },
+        ]);
+        let mut client = Client::with_transport(transport, Timeouts::default()).await;
+        assert_eq!(
+            client.thread(ThreadAlgorithm::References).await,
+            Err(ThreadError {
+                text: "THREAD=REFERENCES is not supported".to_string()
+            })
+        );
+        server.await.expect("client should follow the script");
+    }
+
+    #[tokio::test]
+    async fn stop_at_completion_of_unknown_command() {
+        let (mut transport, server) = serve(vec![Step {
+            command: None,
+            responses: "* BYE going away\r\na0009 OK stale\r\na0001 OK done\r\n",
+        }]);
+        server.await.expect("server should send the script");
+        assert_eq!(
+            read_until_tagged(&mut transport.reader, "a0001", None).await,
+            Err(ReadError::ForeignCompletion)
+        );
+    }
+
+    #[tokio::test]
+    async fn connect_and_logout_from_gmail() {
+        let (transport, server) = serve(vec![
+            Step {
+                command: None,
+                responses: GMAIL_GREETING,
+            },
+            Step {
+                command: Some("a0001 CAPABILITY"),
+                responses: GMAIL_CAPABILITY,
+            },
+            Step {
+                command: None,
+                responses: "a0001 OK Thats all she wrote! a1b2c3d4\r\n",
+            },
+            Step {
+                command: Some("a0002 LOGOUT"),
+                responses: "* BYE LOGOUT Requested\r\na0002 OK 73 good day (Success) a1b2c3d4\r\n",
+            },
+        ]);
+        Client::with_transport(transport, Timeouts::default())
+            .await
+            .logout()
+            .await;
+        server.await.expect("client should follow the script");
+    }
+
+    #[tokio::test]
+    async fn speak_imap_through_tunnel_command() {
+        let config: Config = toml::from_str(
+            r#"
+            user = "me"
+            password_cmd = "pass mail"
+            host = "example.org"
+            port = 993
+            tunnel = "printf '* PREAUTH ready\\r\\n'; read command; printf '* CAPABILITY IMAP4rev1\\r\\na0001 OK done\\r\\n'"
+            "#,
+        )
+        .expect("config should be parseable");
+        let client = Client::new(&config).await;
+        assert!(client.authenticated);
+        assert!(client.tunnel.is_some());
+    }
 }
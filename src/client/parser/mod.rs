@@ -1,23 +1,1038 @@
 mod spec;
 
+use std::{
+    fmt::{self, Display, Formatter},
+    ops::RangeInclusive,
+};
+
+use super::ImplausibleError;
+use chrono::{DateTime, FixedOffset};
 use nom::Finish;
-pub use spec::Capability;
-use spec::{greeting, ResponseTextCode};
+use spec::{
+    greeting, response_data, response_done, Body, BodyType1Part, MailboxData, MailboxList,
+    MessageAttribute, MessageData, MessageDataType, ResponseCondState, ResponseLine, ResponseText,
+    ResponseTextCode, SectionSpec, StatusAttribute, TaggedResponse, Vanished,
+};
+pub use spec::{is_astring_char, Capability, Flag, GreetingStatus, Quota, Status, Thread};
 
-// Todo: distinguish ok, preauth and bye
 #[derive(Debug)]
 pub struct Greeting<'a> {
+    pub status: GreetingStatus,
+    pub text: &'a str,
+    #[allow(dead_code)]
     capabilities: Option<Vec<Capability<'a>>>,
+    // text of an [ALERT], which must be presented to the user
+    pub alert: Option<&'a str>,
 }
-pub fn parse_greeting(input: &str) -> Result<Greeting, ()> {
-    if let Ok((_, response)) = greeting(input).finish() {
-        let capabilities = if let Some(ResponseTextCode::Capability(capabilities)) = response.code {
-            Some(capabilities)
-        } else {
-            None
+pub fn parse_greeting(input: &str) -> Result<Greeting<'_>, ()> {
+    if let Ok((_, (status, response))) = greeting(input).finish() {
+        let mut capabilities = None;
+        let mut alert = None;
+        match response.code {
+            Some(ResponseTextCode::Capability(advertised)) => capabilities = Some(advertised),
+            Some(ResponseTextCode::Alert) => alert = Some(response.text),
+            _ => {}
+        }
+        Ok(Greeting {
+            status,
+            text: response.text,
+            capabilities,
+            alert,
+        })
+    } else {
+        Err(())
+    }
+}
+
+// parses the untagged response to CAPABILITY, or a completion advertising them in its code, as
+// servers commonly do after LOGIN
+pub fn parse_capabilities(input: &str) -> Result<Vec<Capability<'_>>, ()> {
+    if let Ok((_, ResponseLine::CapabilityData(capabilities))) = response_data(input).finish() {
+        Ok(capabilities)
+    } else if let Ok((
+        _,
+        ResponseLine::Tagged(TaggedResponse {
+            state:
+                ResponseCondState {
+                    text:
+                        ResponseText {
+                            code: Some(ResponseTextCode::Capability(capabilities)),
+                            ..
+                        },
+                    ..
+                },
+            ..
+        }),
+    )) = response_done(input).finish()
+    {
+        Ok(capabilities)
+    } else {
+        Err(())
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct MessagePart {
+    // part specifier as used in BODY[<part>], e.g. [2, 1] for 2.1
+    pub part: Vec<u32>,
+    pub media_type: String,
+    pub media_subtype: String,
+    pub octets: u32,
+    pub filename: Option<String>,
+}
+
+#[derive(Debug, PartialEq)]
+pub struct MessageStructure {
+    pub uid: Option<u32>,
+    pub parts: Vec<MessagePart>,
+}
+pub fn parse_body_structure(input: &str) -> Result<MessageStructure, ()> {
+    if let Ok((
+        _,
+        ResponseLine::MessageData(MessageData {
+            data: MessageDataType::Fetch(attributes),
+            ..
+        }),
+    )) = response_data(input).finish()
+    {
+        let mut uid = None;
+        let mut parts = Vec::new();
+        for attribute in attributes {
+            match attribute {
+                MessageAttribute::Uid(id) => uid = Some(id),
+                MessageAttribute::BodyStructure(body) => collect_parts(&body, &[], &mut parts),
+                _ => {}
+            }
+        }
+        Ok(MessageStructure { uid, parts })
+    } else {
+        Err(())
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct PartialBody<'a> {
+    pub uid: Option<u32>,
+    pub part: Vec<u32>,
+    pub origin: Option<u32>,
+    pub data: Option<&'a str>,
+}
+// parses the response to UID FETCH <uid> BODY.PEEK[<part>]<<start>.<count>>
+pub fn parse_body_section(input: &str) -> Result<PartialBody<'_>, ()> {
+    if let Ok((
+        _,
+        ResponseLine::MessageData(MessageData {
+            data: MessageDataType::Fetch(attributes),
+            ..
+        }),
+    )) = response_data(input).finish()
+    {
+        let mut uid = None;
+        let mut body = None;
+        for attribute in attributes {
+            match attribute {
+                MessageAttribute::Uid(id) => uid = Some(id),
+                MessageAttribute::BodySection {
+                    section,
+                    origin,
+                    data,
+                } => {
+                    let part = match section {
+                        Some(SectionSpec::SectionPart { part, text: None }) => part,
+                        _ => return Err(()),
+                    };
+                    body = Some((part, origin, data));
+                }
+                _ => {}
+            }
+        }
+        let (part, origin, data) = body.ok_or(())?;
+        Ok(PartialBody {
+            uid,
+            part,
+            origin,
+            data,
+        })
+    } else {
+        Err(())
+    }
+}
+
+#[derive(Debug, PartialEq)]
+#[allow(dead_code)]
+pub struct FetchedMail<'a> {
+    pub uid: u32,
+    pub flags: Vec<Flag<'a>>,
+    pub modseq: Option<u64>,
+    // only sent by servers advertising X-GM-EXT-1
+    pub gmail_message_id: Option<u64>,
+    pub gmail_labels: Vec<&'a str>,
+    // only if fetched
+    pub internal_date: Option<DateTime<FixedOffset>>,
+    pub content: &'a str,
+}
+// parses the response to UID FETCH <sequence_set> (UID FLAGS BODY.PEEK[]), or RFC822 instead of
+// BODY.PEEK[]
+// attributes beyond those are accepted but ignored
+#[allow(dead_code)]
+pub fn parse_fetched_mail(input: &str) -> Result<FetchedMail<'_>, ()> {
+    if let Ok((
+        _,
+        ResponseLine::MessageData(MessageData {
+            data: MessageDataType::Fetch(attributes),
+            ..
+        }),
+    )) = response_data(input).finish()
+    {
+        let mut uid = None;
+        let mut flags = None;
+        let mut modseq = None;
+        let mut gmail_message_id = None;
+        let mut gmail_labels = Vec::new();
+        let mut internal_date = None;
+        let mut content = None;
+        // servers are free to order the attributes as they like
+        for attribute in attributes {
+            match attribute {
+                MessageAttribute::Uid(id) => uid = Some(id),
+                MessageAttribute::Flags(fetched_flags) => flags = Some(fetched_flags),
+                MessageAttribute::ModSeq(value) => modseq = Some(value),
+                MessageAttribute::GmailMessageId(id) => gmail_message_id = Some(id),
+                MessageAttribute::GmailLabels(labels) => gmail_labels = labels,
+                MessageAttribute::InternalDate(date) => internal_date = Some(date),
+                // a NIL body is stored as an empty mail
+                MessageAttribute::Rfc822(body)
+                | MessageAttribute::BodySection {
+                    section: None,
+                    origin: None,
+                    data: body,
+                } => content = Some(body.unwrap_or_default()),
+                _ => {}
+            }
+        }
+        Ok(FetchedMail {
+            uid: uid.ok_or(())?,
+            flags: flags.ok_or(())?,
+            modseq,
+            gmail_message_id,
+            gmail_labels,
+            internal_date,
+            content: content.ok_or(())?,
+        })
+    } else {
+        Err(())
+    }
+}
+
+#[allow(dead_code)]
+pub fn parse_quota(input: &str) -> Result<Quota<'_>, ()> {
+    if let Ok((_, ResponseLine::Quota(quota))) = response_data(input).finish() {
+        Ok(quota)
+    } else {
+        Err(())
+    }
+}
+
+// parses the untagged OK sent on SELECT listing the flags the server stores permanently
+#[allow(dead_code)]
+pub fn parse_permanent_flags(input: &str) -> Result<Vec<Flag<'_>>, ()> {
+    if let Ok((
+        _,
+        ResponseLine::CondState(ResponseCondState {
+            text:
+                ResponseText {
+                    code: Some(ResponseTextCode::PermanentFlags(flags)),
+                    ..
+                },
+            ..
+        }),
+    )) = response_data(input).finish()
+    {
+        Ok(flags)
+    } else {
+        Err(())
+    }
+}
+
+// parses the untagged OK sent on SELECT announcing the uid the next mail will get
+pub fn parse_uid_next(input: &str) -> Result<u32, ()> {
+    if let Ok((
+        _,
+        ResponseLine::CondState(ResponseCondState {
+            text:
+                ResponseText {
+                    code: Some(ResponseTextCode::UidNext(uid_next)),
+                    ..
+                },
+            ..
+        }),
+    )) = response_data(input).finish()
+    {
+        Ok(uid_next)
+    } else {
+        Err(())
+    }
+}
+
+// parses the untagged OK sent on SELECT with CONDSTORE, modseqs exceed 32 bits in practice
+#[allow(dead_code)]
+pub fn parse_highest_modseq(input: &str) -> Result<u64, ()> {
+    if let Ok((
+        _,
+        ResponseLine::CondState(ResponseCondState {
+            text:
+                ResponseText {
+                    code: Some(ResponseTextCode::HighestModSeq(modseq)),
+                    ..
+                },
+            ..
+        }),
+    )) = response_data(input).finish()
+    {
+        Ok(modseq)
+    } else {
+        Err(())
+    }
+}
+
+// setting a flag the server won't keep yields NO, \* allows creating new keywords
+#[allow(dead_code)]
+pub fn is_permanent(flag: &Flag, permanent_flags: &[Flag]) -> bool {
+    permanent_flags.contains(flag)
+        || matches!(flag, Flag::Keyword(_)) && permanent_flags.contains(&Flag::Wildcard)
+}
+
+#[derive(Debug, PartialEq)]
+#[allow(dead_code)]
+pub struct ListedMailbox<'a> {
+    // still modified UTF-7 encoded, as sent by the server
+    pub name: &'a str,
+    pub delimiter: char,
+    pub selectable: bool,
+}
+// parses a LIST or LSUB response
+// a NIL delimiter means the server has no hierarchy, so any delimiter is as good as /
+#[allow(dead_code)]
+pub fn parse_list(input: &str) -> Result<ListedMailbox<'_>, ()> {
+    if let Ok((
+        _,
+        ResponseLine::MailboxData(
+            MailboxData::List(MailboxList {
+                attributes,
+                delimiter,
+                name,
+            })
+            | MailboxData::Lsub(MailboxList {
+                attributes,
+                delimiter,
+                name,
+            }),
+        ),
+    )) = response_data(input).finish()
+    {
+        Ok(ListedMailbox {
+            name,
+            delimiter: delimiter.unwrap_or('/'),
+            selectable: !attributes
+                .iter()
+                .any(|attribute| attribute.eq_ignore_ascii_case("\\Noselect")),
+        })
+    } else {
+        Err(())
+    }
+}
+
+pub fn parse_thread(input: &str) -> Result<Vec<Thread>, ()> {
+    if let Ok((_, ResponseLine::MailboxData(MailboxData::Thread(threads)))) =
+        response_data(input).finish()
+    {
+        Ok(threads)
+    } else {
+        Err(())
+    }
+}
+
+#[derive(Debug, PartialEq, Default)]
+#[allow(dead_code)]
+pub struct MailboxStatus<'a> {
+    pub name: &'a str,
+    pub messages: Option<u32>,
+    pub uid_next: Option<u32>,
+    pub uid_validity: Option<u32>,
+    pub unseen: Option<u32>,
+    pub highest_modseq: Option<u64>,
+}
+impl MailboxStatus<'_> {
+    // new mail moves UIDNEXT, flag changes and expunges HIGHESTMODSEQ, so a mailbox whose status
+    // stayed the same doesn't need to be selected
+    // without HIGHESTMODSEQ only the message count hints at expunges, flag changes go unnoticed
+    #[allow(dead_code)]
+    pub fn unchanged_since(&self, previous: &MailboxStatus) -> bool {
+        self.uid_validity == previous.uid_validity
+            && self.uid_next == previous.uid_next
+            && self.messages == previous.messages
+            && self.highest_modseq == previous.highest_modseq
+    }
+}
+
+// parses a STATUS response, either to STATUS or to LIST ... RETURN (STATUS ...)
+// only the requested attributes are set
+#[allow(dead_code)]
+pub fn parse_status(input: &str) -> Result<MailboxStatus<'_>, ()> {
+    if let Ok((_, ResponseLine::MailboxData(MailboxData::Status(name, attributes)))) =
+        response_data(input).finish()
+    {
+        let mut status = MailboxStatus {
+            name,
+            ..Default::default()
         };
-        Ok(Greeting { capabilities })
+        for attribute in attributes {
+            match attribute {
+                StatusAttribute::Messages(messages) => status.messages = Some(messages),
+                StatusAttribute::UidNext(uid_next) => status.uid_next = Some(uid_next),
+                StatusAttribute::UidValidity(uid_validity) => {
+                    status.uid_validity = Some(uid_validity)
+                }
+                StatusAttribute::Unseen(unseen) => status.unseen = Some(unseen),
+                StatusAttribute::HighestModSeq(modseq) => status.highest_modseq = Some(modseq),
+                StatusAttribute::Recent(_) => {}
+            }
+        }
+        Ok(status)
+    } else {
+        Err(())
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Completion<'a> {
+    pub status: Status,
+    pub text: &'a str,
+}
+// parses the tagged completion of any command
+pub fn parse_completion(input: &str) -> Result<Completion<'_>, ()> {
+    if let Ok((
+        _,
+        ResponseLine::Tagged(TaggedResponse {
+            state: ResponseCondState { status, text },
+            ..
+        }),
+    )) = response_done(input).finish()
+    {
+        Ok(Completion {
+            status,
+            text: text.text,
+        })
+    } else {
+        Err(())
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct AppendUid {
+    pub uid_validity: u32,
+    // in the order the messages were appended
+    pub uids: Vec<RangeInclusive<u32>>,
+}
+
+impl AppendUid {
+    // pairs the appended mails with their uids, unless the server reported a different number of
+    // uids, in which case guessing would record wrong uids and the mails are handed back to be
+    // looked up by Message-ID
+    #[allow(dead_code)]
+    pub fn assign<T>(&self, mails: Vec<T>) -> Result<Vec<(u32, T)>, Vec<T>> {
+        let count: usize = self.uids.iter().map(|range| range.clone().count()).sum();
+        if count == mails.len() {
+            Ok(self.uids.iter().cloned().flatten().zip(mails).collect())
+        } else {
+            Err(mails)
+        }
+    }
+}
+// parses the tagged completion of APPEND, servers without UIDPLUS don't report the assigned uids,
+// so they have to be looked up by Message-ID instead
+pub fn parse_append_uid(input: &str) -> Result<Option<AppendUid>, ()> {
+    if let Ok((
+        _,
+        ResponseLine::Tagged(TaggedResponse {
+            state:
+                ResponseCondState {
+                    status: Status::Ok,
+                    text: ResponseText { code, .. },
+                },
+            ..
+        }),
+    )) = response_done(input).finish()
+    {
+        if let Some(ResponseTextCode::AppendUid(uid_validity, uids)) = code {
+            Ok(Some(AppendUid { uid_validity, uids }))
+        } else {
+            Ok(None)
+        }
+    } else {
+        Err(())
+    }
+}
+
+// as sent in commands, e.g. to compare with flags from the config
+impl Display for Flag<'_> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Flag::Answered => write!(f, "\\Answered"),
+            Flag::Flagged => write!(f, "\\Flagged"),
+            Flag::Deleted => write!(f, "\\Deleted"),
+            Flag::Seen => write!(f, "\\Seen"),
+            Flag::Draft => write!(f, "\\Draft"),
+            Flag::Keyword(keyword) => write!(f, "{keyword}"),
+            Flag::Extension(extension) => write!(f, "\\{extension}"),
+            Flag::Wildcard => write!(f, "\\*"),
+            Flag::Recent => write!(f, "\\Recent"),
+        }
+    }
+}
+
+impl Flag<'_> {
+    // letters of the maildir info suffix, https://cr.yp.to/proto/maildir.html
+    // \Recent is managed by the server and is neither written to disk nor pushed, keywords are
+    // mapped by the configured keyword letters, e.g. Config::keyword_letter of the mailbox
+    pub fn maildir_letter(&self, keyword_letter: impl Fn(&str) -> Option<char>) -> Option<char> {
+        match self {
+            Flag::Draft => Some('D'),
+            Flag::Flagged => Some('F'),
+            Flag::Answered => Some('R'),
+            Flag::Seen => Some('S'),
+            Flag::Deleted => Some('T'),
+            // lowercase letters are reserved for keywords, so a letter can't pass for a system flag
+            Flag::Keyword(keyword) => keyword_letter(keyword).filter(char::is_ascii_lowercase),
+            Flag::Recent | Flag::Wildcard | Flag::Extension(_) => None,
+        }
+    }
+
+    #[allow(dead_code)]
+    pub fn from_maildir_letter(letter: char) -> Option<Self> {
+        match letter {
+            'D' => Some(Flag::Draft),
+            'F' => Some(Flag::Flagged),
+            'R' => Some(Flag::Answered),
+            'S' => Some(Flag::Seen),
+            'T' => Some(Flag::Deleted),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct MailMetadata<'a> {
+    pub uid: u32,
+    pub flags: Vec<Flag<'a>>,
+    pub modseq: Option<u64>,
+}
+impl MailMetadata<'_> {
+    // uids are assigned in ascending order, so a uid from UIDNEXT on can't exist yet
+    // the caller decides whether and how to report the reason
+    pub fn check_plausible(&self, uid_next: u32) -> Result<(), ImplausibleError> {
+        if self.uid < uid_next {
+            Ok(())
+        } else {
+            Err(ImplausibleError {
+                text: format!("uid {} is not below UIDNEXT {uid_next}", self.uid),
+            })
+        }
+    }
+}
+
+// parses the response to UID FETCH <sequence_set> (UID FLAGS MODSEQ)
+pub fn parse_mail_metadata(input: &str) -> Result<MailMetadata<'_>, ()> {
+    if let Ok((
+        _,
+        ResponseLine::MessageData(MessageData {
+            data: MessageDataType::Fetch(attributes),
+            ..
+        }),
+    )) = response_data(input).finish()
+    {
+        let mut uid = None;
+        let mut flags = None;
+        let mut modseq = None;
+        for attribute in attributes {
+            match attribute {
+                MessageAttribute::Uid(id) => uid = Some(id),
+                MessageAttribute::Flags(fetched_flags) => flags = Some(fetched_flags),
+                MessageAttribute::ModSeq(value) => modseq = Some(value),
+                _ => {}
+            }
+        }
+        Ok(MailMetadata {
+            uid: uid.ok_or(())?,
+            flags: flags.ok_or(())?,
+            modseq,
+        })
     } else {
         Err(())
     }
 }
+
+// mail deleted by another client, which servers may report in between the responses to any command
+#[derive(Debug, PartialEq)]
+#[allow(dead_code)]
+pub enum Expunged {
+    // all following sequence numbers shift down by one, so these have to be applied in order
+    SequenceNumber(u32),
+    // sent instead of EXPUNGE once QRESYNC is enabled
+    Uids(Vec<RangeInclusive<u32>>),
+}
+#[allow(dead_code)]
+pub fn parse_expunged(input: &str) -> Result<Expunged, ()> {
+    match response_data(input).finish() {
+        Ok((
+            _,
+            ResponseLine::MessageData(MessageData {
+                sequence_number,
+                data: MessageDataType::Expunge,
+            }),
+        )) => Ok(Expunged::SequenceNumber(sequence_number)),
+        Ok((_, ResponseLine::Vanished(Vanished { uids, .. }))) => Ok(Expunged::Uids(uids)),
+        _ => Err(()),
+    }
+}
+
+fn collect_parts(body: &Body, prefix: &[u32], parts: &mut Vec<MessagePart>) {
+    match body {
+        Body::SinglePart {
+            body_type,
+            extension,
+        } => {
+            let (media_type, media_subtype, body_fields) = match body_type {
+                BodyType1Part::Basic {
+                    media_type,
+                    media_subtype,
+                    body_fields,
+                } => (*media_type, *media_subtype, body_fields),
+                BodyType1Part::Message { body_fields, .. } => ("message", "rfc822", body_fields),
+                BodyType1Part::Text {
+                    media_subtype,
+                    body_fields,
+                    ..
+                } => ("text", *media_subtype, body_fields),
+            };
+            let filename = extension
+                .as_ref()
+                .and_then(|extension| extension.extension.dsp.as_ref())
+                .and_then(|dsp| find_param(&dsp.param, "filename"))
+                .or_else(|| find_param(&body_fields.param, "name"));
+            // a non-multipart message only has part 1
+            let part = if prefix.is_empty() {
+                vec![1]
+            } else {
+                prefix.to_vec()
+            };
+            parts.push(MessagePart {
+                part,
+                media_type: media_type.to_string(),
+                media_subtype: media_subtype.to_string(),
+                octets: body_fields.octets,
+                filename: filename.map(str::to_string),
+            });
+        }
+        Body::MultiPart { bodies, .. } => {
+            for (number, body) in (1..).zip(bodies) {
+                let mut part = prefix.to_vec();
+                part.push(number);
+                collect_parts(body, &part, parts);
+            }
+        }
+    }
+}
+
+fn find_param<'a>(params: &[(&'a str, &'a str)], key: &str) -> Option<&'a str> {
+    params
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case(key))
+        .map(|(_, value)| *value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use spec::QuotaResource;
+
+    #[test]
+    fn list_attachments_of_mixed_message() {
+        let response = "* 1 FETCH (UID 12 BODYSTRUCTURE ((\"text\" \"plain\" (\"charset\" \"utf-8\") NIL NIL \"quoted-printable\" 1423 32 NIL NIL NIL NIL)(\"application\" \"pdf\" (\"name\" \"invoice.pdf\") NIL NIL \"base64\" 85672 NIL (\"attachment\" (\"filename\" \"invoice 2024.pdf\")) NIL NIL) \"mixed\" (\"boundary\" \"------------0D1A2F\") NIL NIL NIL))\r\n";
+        let structure = parse_body_structure(response).expect("body structure should be parseable");
+        assert_eq!(
+            structure,
+            MessageStructure {
+                uid: Some(12),
+                parts: vec![
+                    MessagePart {
+                        part: vec![1],
+                        media_type: "text".to_string(),
+                        media_subtype: "plain".to_string(),
+                        octets: 1423,
+                        filename: None,
+                    },
+                    MessagePart {
+                        part: vec![2],
+                        media_type: "application".to_string(),
+                        media_subtype: "pdf".to_string(),
+                        octets: 85672,
+                        filename: Some("invoice 2024.pdf".to_string()),
+                    },
+                ],
+            }
+        );
+    }
+
+    #[test]
+    fn number_parts_of_nested_multipart_message() {
+        // multipart/mixed containing multipart/alternative and an attachment, as sent by most MUAs
+        let response = "* 1 FETCH (UID 13 BODYSTRUCTURE (((\"text\" \"plain\" (\"charset\" \"utf-8\") NIL NIL \"7bit\" 120 4 NIL NIL NIL NIL)(\"text\" \"html\" (\"charset\" \"utf-8\") NIL NIL \"quoted-printable\" 830 17 NIL NIL NIL NIL) \"alternative\" (\"boundary\" \"b2\") NIL NIL NIL)(\"image\" \"png\" (\"name\" \"logo.png\") NIL NIL \"base64\" 4096 NIL (\"inline\" NIL) NIL NIL) \"mixed\" (\"boundary\" \"b1\") NIL NIL NIL))\r\n";
+        let structure = parse_body_structure(response).expect("body structure should be parseable");
+        let parts: Vec<_> = structure
+            .parts
+            .iter()
+            .map(|part| {
+                (
+                    part.part.clone(),
+                    part.media_subtype.as_str(),
+                    part.filename.as_deref(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            parts,
+            [
+                (vec![1, 1], "plain", None),
+                (vec![1, 2], "html", None),
+                (vec![2], "png", Some("logo.png")),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_partial_attachment() {
+        let response = "* 1 FETCH (UID 12 BODY[2]<1024> {8}\r\nJVBERi0x)\r\n";
+        let body = parse_body_section(response).expect("body section should be parseable");
+        assert_eq!(
+            body,
+            PartialBody {
+                uid: Some(12),
+                part: vec![2],
+                origin: Some(1024),
+                data: Some("JVBERi0x"),
+            }
+        );
+    }
+
+    #[test]
+    fn parse_mail_with_reordered_attributes() {
+        let response = "* 3 FETCH (RFC822 {12}\r\nSubject: a\r\n FLAGS (\\Seen) UID 42)\r\n";
+        let mail = parse_fetched_mail(response).expect("fetched mail should be parseable");
+        assert_eq!(
+            mail,
+            FetchedMail {
+                uid: 42,
+                flags: vec![Flag::Seen],
+                modseq: None,
+                gmail_message_id: None,
+                gmail_labels: vec![],
+                internal_date: None,
+                content: "Subject: a\r\n",
+            }
+        );
+    }
+
+    #[test]
+    fn parse_mail_with_nil_body() {
+        let response = "* 3 FETCH (UID 42 FLAGS () RFC822 NIL)\r\n";
+        let mail = parse_fetched_mail(response).expect("fetched mail should be parseable");
+        assert_eq!(
+            mail,
+            FetchedMail {
+                uid: 42,
+                flags: vec![],
+                modseq: None,
+                gmail_message_id: None,
+                gmail_labels: vec![],
+                internal_date: None,
+                content: "",
+            }
+        );
+    }
+
+    #[test]
+    fn parse_mail_with_interleaved_attributes() {
+        let response = "* 3 FETCH (MODSEQ (12345678901) INTERNALDATE \"17-Jul-1996 02:44:25 -0700\" UID 42 RFC822.SIZE 12 FLAGS (\\Answered $Forwarded) RFC822 {12}\r\nSubject: a\r\n)\r\n";
+        let mail = parse_fetched_mail(response).expect("fetched mail should be parseable");
+        assert_eq!(
+            mail,
+            FetchedMail {
+                uid: 42,
+                flags: vec![Flag::Answered, Flag::Keyword("$Forwarded")],
+                modseq: Some(12345678901),
+                gmail_message_id: None,
+                gmail_labels: vec![],
+                internal_date: DateTime::parse_from_rfc2822("Wed, 17 Jul 1996 02:44:25 -0700").ok(),
+                content: "Subject: a\r\n",
+            }
+        );
+    }
+
+    #[test]
+    fn parse_mail_fetched_with_body_peek() {
+        let response = "* 3 FETCH (UID 42 FLAGS () BODY[] {12}\r\nSubject: a\r\n)\r\n";
+        let mail = parse_fetched_mail(response).expect("fetched mail should be parseable");
+        assert_eq!(mail.content, "Subject: a\r\n");
+        let response = "* 3 FETCH (UID 42 FLAGS () BODY[TEXT] {3}\r\nabc)\r\n";
+        assert_eq!(parse_fetched_mail(response), Err(()));
+    }
+
+    #[test]
+    fn parse_mail_with_gmail_attributes() {
+        let response = "* 3 FETCH (X-GM-MSGID 1278455344230334865 X-GM-LABELS (\\Inbox \\Important \"Muy Importante\" Work) UID 42 FLAGS () RFC822 NIL)\r\n";
+        let mail = parse_fetched_mail(response).expect("fetched mail should be parseable");
+        assert_eq!(mail.gmail_message_id, Some(1278455344230334865));
+        assert_eq!(
+            mail.gmail_labels,
+            vec!["\\Inbox", "\\Important", "Muy Importante", "Work"]
+        );
+    }
+
+    #[test]
+    fn parse_metadata_with_modseq() {
+        let response = "* 7 FETCH (UID 42 MODSEQ (4294967302) FLAGS (\\Seen))\r\n";
+        let metadata = parse_mail_metadata(response).expect("metadata should be parseable");
+        assert_eq!(
+            metadata,
+            MailMetadata {
+                uid: 42,
+                flags: vec![Flag::Seen],
+                modseq: Some(4294967302),
+            }
+        );
+    }
+
+    #[test]
+    fn reject_mail_without_uid() {
+        let response = "* 3 FETCH (FLAGS (\\Seen) RFC822 NIL)\r\n";
+        assert_eq!(parse_fetched_mail(response), Err(()));
+    }
+
+    #[test]
+    fn parse_greeting_with_alert() {
+        let response = "* OK [ALERT] Mailbox is at 98% of quota\r\n";
+        let greeting = parse_greeting(response).expect("greeting should be parseable");
+        assert_eq!(greeting.alert, Some("Mailbox is at 98% of quota"));
+    }
+
+    #[test]
+    fn parse_storage_quota() {
+        let response = "* QUOTA \"\" (STORAGE 10 512 MESSAGE 3 1000)\r\n";
+        let quota = parse_quota(response).expect("quota should be parseable");
+        assert_eq!(
+            quota,
+            Quota {
+                root: "",
+                resources: vec![
+                    QuotaResource {
+                        name: "STORAGE",
+                        usage: 10,
+                        limit: 512,
+                    },
+                    QuotaResource {
+                        name: "MESSAGE",
+                        usage: 3,
+                        limit: 1000,
+                    },
+                ],
+            }
+        );
+    }
+
+    #[test]
+    fn skip_flags_the_server_cannot_store() {
+        let permanent_flags =
+            parse_permanent_flags("* OK [PERMANENTFLAGS (\\Seen \\Deleted)] Limited\r\n")
+                .expect("permanent flags should be parseable");
+        assert!(is_permanent(&Flag::Seen, &permanent_flags));
+        assert!(!is_permanent(&Flag::Draft, &permanent_flags));
+        assert!(!is_permanent(&Flag::Keyword("$Junk"), &permanent_flags));
+
+        let permanent_flags = parse_permanent_flags("* OK [PERMANENTFLAGS (\\Seen \\*)] Ok\r\n")
+            .expect("permanent flags should be parseable");
+        assert!(is_permanent(&Flag::Keyword("$Junk"), &permanent_flags));
+    }
+
+    #[test]
+    fn parse_uids_of_appended_mail() {
+        assert_eq!(
+            parse_append_uid("abcd OK [APPENDUID 38505 3955:3956] APPEND completed\r\n"),
+            Ok(Some(AppendUid {
+                uid_validity: 38505,
+                uids: vec![3955..=3956]
+            }))
+        );
+        assert_eq!(parse_append_uid("abcd OK APPEND completed\r\n"), Ok(None));
+        assert_eq!(
+            parse_append_uid("abcd NO [TRYCREATE] no such mailbox\r\n"),
+            Err(())
+        );
+    }
+
+    #[test]
+    fn refuse_to_guess_uids_of_appended_mail() {
+        let append_uid = AppendUid {
+            uid_validity: 38505,
+            uids: vec![3955..=3956, 3960..=3960],
+        };
+        assert_eq!(
+            append_uid.assign(vec!["a", "b", "c"]),
+            Ok(vec![(3955, "a"), (3956, "b"), (3960, "c")])
+        );
+        assert_eq!(
+            append_uid.assign(vec!["a", "b", "c", "d"]),
+            Err(vec!["a", "b", "c", "d"])
+        );
+    }
+
+    #[test]
+    fn default_delimiter_of_flat_hierarchy() {
+        assert_eq!(
+            parse_list("* LIST (\\Noselect) NIL Archive\r\n"),
+            Ok(ListedMailbox {
+                name: "Archive",
+                delimiter: '/',
+                selectable: false
+            })
+        );
+    }
+
+    #[test]
+    fn map_configured_keywords_to_letters() {
+        let keyword_letter = |keyword: &str| match keyword {
+            "$Forwarded" => Some('a'),
+            "$Junk" => Some('J'),
+            _ => None,
+        };
+        assert_eq!(
+            Flag::Keyword("$Forwarded").maildir_letter(keyword_letter),
+            Some('a')
+        );
+        assert_eq!(Flag::Keyword("$Junk").maildir_letter(keyword_letter), None);
+        assert_eq!(
+            Flag::Keyword("$NotJunk").maildir_letter(keyword_letter),
+            None
+        );
+        assert_eq!(Flag::Seen.maildir_letter(keyword_letter), Some('S'));
+    }
+
+    #[test]
+    fn never_store_recent_locally() {
+        let metadata = parse_mail_metadata("* 1 FETCH (UID 7 FLAGS (\\Recent \\Flagged))\r\n")
+            .expect("metadata should be parseable");
+        let letters: Vec<char> = metadata
+            .flags
+            .iter()
+            .filter_map(|flag| flag.maildir_letter(|_| None))
+            .collect();
+        assert_eq!(letters, vec!['F']);
+        assert_eq!(Flag::from_maildir_letter('F'), Some(Flag::Flagged));
+        assert_eq!(Flag::from_maildir_letter('a'), None);
+    }
+
+    #[test]
+    fn detect_uids_beyond_uid_next() {
+        let uid_next = parse_uid_next("* OK [UIDNEXT 4392] Predicted next UID\r\n")
+            .expect("uid next should be parseable");
+        assert_eq!(uid_next, 4392);
+        let metadata = parse_mail_metadata("* 1 FETCH (UID 4391 FLAGS ())\r\n")
+            .expect("metadata should be parseable");
+        assert_eq!(metadata.check_plausible(uid_next), Ok(()));
+        let metadata = parse_mail_metadata("* 2 FETCH (UID 4392 FLAGS ())\r\n")
+            .expect("metadata should be parseable");
+        assert_eq!(
+            metadata.check_plausible(uid_next),
+            Err(ImplausibleError {
+                text: "uid 4392 is not below UIDNEXT 4392".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn tell_apart_expunges_interleaved_with_fetch() {
+        let responses = [
+            "* 1 FETCH (UID 4 FLAGS (\\Seen))\r\n",
+            "* 2 EXPUNGE\r\n",
+            "* 2 FETCH (UID 9 FLAGS ())\r\n",
+            "* VANISHED 12:13\r\n",
+        ];
+        let uids: Vec<_> = responses
+            .iter()
+            .filter_map(|response| parse_mail_metadata(response).ok())
+            .map(|metadata| metadata.uid)
+            .collect();
+        assert_eq!(uids, [4, 9]);
+        let expunged: Vec<_> = responses
+            .iter()
+            .filter_map(|response| parse_expunged(response).ok())
+            .collect();
+        assert_eq!(
+            expunged,
+            [Expunged::SequenceNumber(2), Expunged::Uids(vec![12..=13])]
+        );
+    }
+
+    #[test]
+    fn parse_status_returned_by_list() {
+        assert_eq!(
+            parse_list("* LIST () \"/\" Sent\r\n").map(|listed| listed.name),
+            Ok("Sent")
+        );
+        assert_eq!(
+            parse_status(
+                "* STATUS Sent (MESSAGES 17 UIDNEXT 4392 UIDVALIDITY 3857529045 HIGHESTMODSEQ 90060115205545359)\r\n"
+            ),
+            Ok(MailboxStatus {
+                name: "Sent",
+                messages: Some(17),
+                uid_next: Some(4392),
+                uid_validity: Some(3857529045),
+                unseen: None,
+                highest_modseq: Some(90060115205545359),
+            })
+        );
+    }
+
+    #[test]
+    fn detect_changed_mailbox_by_status() {
+        let previous = parse_status("* STATUS INBOX (MESSAGES 3 UIDNEXT 10 HIGHESTMODSEQ 7)\r\n")
+            .expect("status should be parseable");
+        let unchanged = parse_status("* STATUS INBOX (MESSAGES 3 UIDNEXT 10 HIGHESTMODSEQ 7)\r\n")
+            .expect("status should be parseable");
+        let flagged = parse_status("* STATUS INBOX (MESSAGES 3 UIDNEXT 10 HIGHESTMODSEQ 8)\r\n")
+            .expect("status should be parseable");
+        assert!(unchanged.unchanged_since(&previous));
+        assert!(!flagged.unchanged_since(&previous));
+    }
+
+    #[test]
+    fn keep_highest_modseq_beyond_32_bits() {
+        assert_eq!(
+            parse_highest_modseq("* OK [HIGHESTMODSEQ 90060115205545359] Highest\r\n"),
+            Ok(90060115205545359)
+        );
+        assert_eq!(
+            parse_highest_modseq("* OK [HIGHESTMODSEQ 18446744073709551616] Highest\r\n"),
+            Err(())
+        );
+    }
+
+    #[test]
+    fn render_flags_as_sent() {
+        let metadata = parse_mail_metadata("* 1 FETCH (UID 7 FLAGS (\\Seen $Junk \\Answered))\r\n")
+            .expect("metadata should be parseable");
+        let flags: Vec<String> = metadata.flags.iter().map(Flag::to_string).collect();
+        assert_eq!(flags, vec!["\\Seen", "$Junk", "\\Answered"]);
+    }
+}
@@ -1,9 +1,11 @@
+use std::ops::RangeInclusive;
+
 use chrono::{DateTime, FixedOffset, TimeZone};
 use nom::{
     branch::alt,
-    bytes::complete::{escaped, tag, take, take_while, take_while1},
-    character::complete::{char, crlf, digit0, digit1, one_of},
-    combinator::{all_consuming, map, opt},
+    bytes::complete::{tag, tag_no_case, take, take_while1},
+    character::complete::{char, crlf, digit0, digit1, none_of, one_of},
+    combinator::{all_consuming, map, opt, peek, recognize},
     error::Error,
     multi::{many0, many1, separated_list0, separated_list1},
     sequence::{delimited, pair, preceded, separated_pair, terminated, tuple},
@@ -27,7 +29,6 @@ fn is_char8(input: char) -> bool {
     (input as u32) != 0 && (input as u32) <= 0xff
 }
 
-// technically CTL is missing here
 fn is_atom_special(input: char) -> bool {
     input == '('
         || input == ')'
@@ -36,13 +37,14 @@ fn is_atom_special(input: char) -> bool {
         || is_list_wildcard(input)
         || is_quoted_special(input)
         || is_resp_special(input)
+        || input.is_ascii_control()
 }
 
 fn is_atom_char(input: char) -> bool {
     !is_atom_special(input)
 }
 
-fn is_astring_char(input: char) -> bool {
+pub fn is_astring_char(input: char) -> bool {
     is_atom_char(input) || is_resp_special(input)
 }
 
@@ -59,15 +61,15 @@ fn is_text_char_without_closing_square_bracket(input: char) -> bool {
 }
 
 fn is_not_quoted_special_or_escape(input: char) -> bool {
-    !(input != '\\' || is_quoted_special(input))
+    !(input == '\\' || is_quoted_special(input))
 }
 
 // number represents the number of char8s
 fn literal(input: &str) -> IResult<&str, &str> {
     let (rest, char8_length) = terminated(delimited(char('{'), number, char('}')), crlf)(input)?;
-    let (rest, char8_sequence) = take_while(is_char8)(rest)?;
-    if char8_sequence.len() as u32 == char8_length {
-        Ok((rest, char8_sequence))
+    let char8_length = char8_length as usize;
+    if rest.is_char_boundary(char8_length) && rest[..char8_length].chars().all(is_char8) {
+        Ok((&rest[char8_length..], &rest[..char8_length]))
     } else {
         // ToDo: actually learn, how the error system in nom works
         Err(nom::Err::Error(Error::new(
@@ -79,7 +81,7 @@ fn literal(input: &str) -> IResult<&str, &str> {
 
 #[derive(Debug, PartialEq)]
 pub struct Tag<'a>(&'a str);
-fn imap_tag(input: &str) -> IResult<&str, Tag> {
+fn imap_tag(input: &str) -> IResult<&str, Tag<'_>> {
     map(take_while1(is_astring_char_without_plus), Tag)(input)
 }
 
@@ -88,13 +90,13 @@ fn text(input: &str) -> IResult<&str, &str> {
 }
 
 fn quoted(input: &str) -> IResult<&str, &str> {
+    // nom's escaped gives up on an escape following unescaped characters
     delimited(
         char('"'),
-        escaped(
-            take_while(is_not_quoted_special_or_escape),
-            '\\',
-            one_of(QUOTED_SPECIALS),
-        ),
+        recognize(many0(alt((
+            take_while1(is_not_quoted_special_or_escape),
+            recognize(preceded(char('\\'), one_of(QUOTED_SPECIALS))),
+        )))),
         char('"'),
     )(input)
 }
@@ -111,6 +113,18 @@ fn number(input: &str) -> IResult<&str, u32> {
     }
 }
 
+fn number64(input: &str) -> IResult<&str, u64> {
+    let (rest, raw_number) = digit1(input)?;
+    if let Ok(parsed_number) = raw_number.parse::<u64>() {
+        Ok((rest, parsed_number))
+    } else {
+        Err(nom::Err::Error(Error::new(
+            input,
+            nom::error::ErrorKind::Float,
+        )))
+    }
+}
+
 fn two_digit(input: &str) -> IResult<&str, u32> {
     let (rest, raw_number) = take(2u32).and_then(all_consuming(digit0)).parse(input)?;
     if let Ok(parsed_number) = raw_number.parse::<u32>() {
@@ -144,12 +158,12 @@ fn atom(input: &str) -> IResult<&str, &str> {
 }
 
 pub struct AuthType<'a>(&'a str);
-fn auth_type(input: &str) -> IResult<&str, AuthType> {
+fn auth_type(input: &str) -> IResult<&str, AuthType<'_>> {
     // defined by https://datatracker.ietf.org/doc/html/rfc3501#ref-SASL
     map(atom, AuthType)(input)
 }
 
-fn capability(input: &str) -> IResult<&str, Capability> {
+fn capability(input: &str) -> IResult<&str, Capability<'_>> {
     // New capabilities MUST begin with "X" or be
     // registered with IANA as standard or
     // standards-track
@@ -158,11 +172,12 @@ fn capability(input: &str) -> IResult<&str, Capability> {
             Capability::AuthType(auth.0)
         }),
         map(revision, Capability::Revision),
+        map(tag("LOGINDISABLED"), |_| Capability::LoginDisabled),
         map(atom, Capability::Custom),
     ))(input)
 }
 
-fn capability_data(input: &str) -> IResult<&str, Vec<Capability>> {
+fn capability_data(input: &str) -> IResult<&str, Vec<Capability<'_>>> {
     preceded(
         preceded(tag("CAPABILITY"), space),
         separated_list1(space, capability),
@@ -174,11 +189,11 @@ fn nz_number(input: &str) -> IResult<&str, u32> {
     number(input)
 }
 
-fn flag_keyword(input: &str) -> IResult<&str, Flag> {
+fn flag_keyword(input: &str) -> IResult<&str, Flag<'_>> {
     map(atom, Flag::Keyword)(input)
 }
 
-fn flag_extension(input: &str) -> IResult<&str, Flag> {
+fn flag_extension(input: &str) -> IResult<&str, Flag<'_>> {
     //; Future expansion.  Client implementations
     //; MUST accept flag-extension flags.  Server
     //; implementations MUST NOT generate
@@ -202,7 +217,7 @@ pub enum Flag<'a> {
     // technically flag-fetch, not flag as defined by bakus-naur, but easier to parse
     Recent,
 }
-fn flag(input: &str) -> IResult<&str, Flag> {
+fn flag(input: &str) -> IResult<&str, Flag<'_>> {
     alt((
         map(tag("\\Answered"), |_| Flag::Answered),
         map(tag("\\Flagged"), |_| Flag::Flagged),
@@ -229,6 +244,10 @@ pub enum ResponseTextCode<'a> {
     UidNext(u32),
     UidValidity(u32),
     Unseen(u32),
+    // https://datatracker.ietf.org/doc/html/rfc4315#section-3
+    AppendUid(u32, Vec<RangeInclusive<u32>>),
+    // https://datatracker.ietf.org/doc/html/rfc7162#section-3.1.2.1
+    HighestModSeq(u64),
     Custom(&'a str, Option<&'a str>),
 }
 
@@ -244,22 +263,26 @@ fn resp_text_code(input: &str) -> IResult<&str, ResponseTextCode<'_>> {
         )
         .map(ResponseTextCode::BadCharset),
         capability_data.map(ResponseTextCode::Capability),
-        tag("PARSE").map(|_| ResponseTextCode::Alert),
+        tag("PARSE").map(|_| ResponseTextCode::Parse),
         delimited(
             separated_pair(tag("PERMANENTFLAGS"), space, char('(')),
-            many0(flag),
+            separated_list0(space, flag),
             char(')'),
         )
         .map(ResponseTextCode::PermanentFlags),
-        tag("READ-ONLY").map(|_| ResponseTextCode::Alert),
-        tag("READ-WRITE").map(|_| ResponseTextCode::Alert),
-        tag("TRYCREATE").map(|_| ResponseTextCode::Alert),
+        tag("READ-ONLY").map(|_| ResponseTextCode::ReadOnly),
+        tag("READ-WRITE").map(|_| ResponseTextCode::ReadWrite),
+        tag("TRYCREATE").map(|_| ResponseTextCode::TryCreate),
         separated_pair(tag("UIDNEXT"), space, nz_number)
             .map(|(_, number)| ResponseTextCode::UidNext(number)),
         separated_pair(tag("UIDVALIDITY"), space, nz_number)
             .map(|(_, number)| ResponseTextCode::UidValidity(number)),
         separated_pair(tag("UNSEEN"), space, nz_number)
             .map(|(_, number)| ResponseTextCode::Unseen(number)),
+        tuple((tag("APPENDUID"), space, nz_number, space, sequence_set))
+            .map(|(_, _, uid_validity, _, uids)| ResponseTextCode::AppendUid(uid_validity, uids)),
+        preceded(pair(tag("HIGHESTMODSEQ"), space), mod_sequence_value)
+            .map(ResponseTextCode::HighestModSeq),
         pair(
             atom,
             opt(preceded(
@@ -276,15 +299,15 @@ pub struct ResponseText<'a> {
     pub code: Option<ResponseTextCode<'a>>,
     pub text: &'a str,
 }
-fn resp_text(input: &str) -> IResult<&str, ResponseText> {
+fn resp_text(input: &str) -> IResult<&str, ResponseText<'_>> {
+    let code = || delimited(char('['), resp_text_code, char(']'));
     map(
-        pair(
-            opt(terminated(
-                delimited(char('['), resp_text_code, char(']')),
-                space,
-            )),
-            text,
-        ),
+        alt((
+            separated_pair(code().map(Some), space, text),
+            // servers commonly leave out the text after a code, e.g. "OK [READ-WRITE]"
+            terminated(code().map(Some), peek(crlf)).map(|code| (code, "")),
+            text.map(|text| (None, text)),
+        )),
         |(code, text)| ResponseText { code, text },
     )(input)
 }
@@ -297,16 +320,16 @@ pub enum Status {
 }
 #[derive(Debug, PartialEq)]
 pub struct ResponseCondState<'a> {
-    status: Status,
-    text: ResponseText<'a>,
+    pub status: Status,
+    pub text: ResponseText<'a>,
 }
-fn resp_cond_state(input: &str) -> IResult<&str, ResponseCondState> {
+fn resp_cond_state(input: &str) -> IResult<&str, ResponseCondState<'_>> {
     map(
         separated_pair(
             alt((
                 map(tag("OK"), |_| Status::Ok),
-                map(tag("NO"), |_| Status::Ok),
-                map(tag("BAD"), |_| Status::Ok),
+                map(tag("NO"), |_| Status::No),
+                map(tag("BAD"), |_| Status::Bad),
             )),
             space,
             resp_text,
@@ -319,14 +342,19 @@ fn space(input: &str) -> IResult<&str, char> {
     char(' ')(input)
 }
 
-fn nstring(input: &str) -> IResult<&str, &str> {
-    alt((nil, string))(input)
+fn nstring(input: &str) -> IResult<&str, Option<&str>> {
+    alt((map(nil, |_| None), map(string, Some)))(input)
 }
 
 fn uniqueid(input: &str) -> IResult<&str, u32> {
     nz_number(input) // strictly ascending
 }
 
+fn mod_sequence_value(input: &str) -> IResult<&str, u64> {
+    // defined by https://datatracker.ietf.org/doc/html/rfc7162#section-7
+    number64(input)
+}
+
 fn date_month(input: &str) -> IResult<&str, u32> {
     alt((
         map(tag("Jan"), |_| 1),
@@ -376,10 +404,10 @@ fn zone(input: &str) -> IResult<&str, FixedOffset> {
                 .expect("seconds should be in i32 range");
             match plus_minus {
                 PlusMinus::Plus => {
-                    FixedOffset::west_opt(seconds).expect("west timezone should be parseable")
+                    FixedOffset::east_opt(seconds).expect("east timezone should be parseable")
                 }
                 PlusMinus::Minus => {
-                    FixedOffset::east_opt(seconds).expect("east timezone should be parseable")
+                    FixedOffset::west_opt(seconds).expect("west timezone should be parseable")
                 }
             }
         },
@@ -418,16 +446,16 @@ fn header_list(input: &str) -> IResult<&str, Vec<&str>> {
     )(input)
 }
 
-enum SectionMsgText<'a> {
+#[derive(Debug, PartialEq)]
+pub enum SectionMsgText<'a> {
     Header,
     HeaderFields(Vec<&'a str>),
     HeaderFieldsNot(Vec<&'a str>),
     Text,
 }
-fn section_msgtxt(input: &str) -> IResult<&str, SectionMsgText> {
+fn section_msgtxt(input: &str) -> IResult<&str, SectionMsgText<'_>> {
     // top-level or MESSAGE/RFC822 part
     alt((
-        map(tag("HEADER"), |_| SectionMsgText::Header),
         map(
             separated_pair(
                 pair(tag("HEADER.FIELDS"), opt(tag(".NOT"))),
@@ -435,25 +463,27 @@ fn section_msgtxt(input: &str) -> IResult<&str, SectionMsgText> {
                 header_list,
             ),
             |((_, not), headers)| {
-                if let Some(_) = not {
+                if not.is_some() {
                     SectionMsgText::HeaderFieldsNot(headers)
                 } else {
                     SectionMsgText::HeaderFields(headers)
                 }
             },
         ),
+        map(tag("HEADER"), |_| SectionMsgText::Header),
         map(tag("TEXT"), |_| SectionMsgText::Text),
     ))(input)
 }
 
-enum SectionText<'a> {
+#[derive(Debug, PartialEq)]
+pub enum SectionText<'a> {
     Mime,
     SectionMsgText(SectionMsgText<'a>),
 }
-fn section_text(input: &str) -> IResult<&str, SectionText> {
+fn section_text(input: &str) -> IResult<&str, SectionText<'_>> {
     // tuple(section_part, opt(preceded(char('.'), section_text)))
     alt((
-        map(section_msgtxt, |msgtxt| SectionText::SectionMsgText(msgtxt)),
+        map(section_msgtxt, SectionText::SectionMsgText),
         map(tag("MIME"), |_| SectionText::Mime),
     ))(input)
 }
@@ -463,16 +493,17 @@ fn section_part(input: &str) -> IResult<&str, Vec<u32>> {
     separated_list1(char('.'), nz_number)(input)
 }
 
-enum SectionSpec<'a> {
+#[derive(Debug, PartialEq)]
+pub enum SectionSpec<'a> {
     SectionMsgText(SectionMsgText<'a>),
     SectionPart {
         part: Vec<u32>,
         text: Option<SectionText<'a>>,
     },
 }
-fn section_spec(input: &str) -> IResult<&str, SectionSpec> {
+fn section_spec(input: &str) -> IResult<&str, SectionSpec<'_>> {
     alt((
-        map(section_msgtxt, |msgtxt| SectionSpec::SectionMsgText(msgtxt)),
+        map(section_msgtxt, SectionSpec::SectionMsgText),
         map(
             pair(section_part, opt(preceded(char('.'), section_text))),
             |(part, text)| SectionSpec::SectionPart { part, text },
@@ -480,39 +511,54 @@ fn section_spec(input: &str) -> IResult<&str, SectionSpec> {
     ))(input)
 }
 
-fn section(input: &str) -> IResult<&str, Option<SectionSpec>> {
+fn section(input: &str) -> IResult<&str, Option<SectionSpec<'_>>> {
     delimited(char('['), opt(section_spec), char(']'))(input)
 }
 
-fn resp_cond_auth(input: &str) -> IResult<&str, ResponseText> {
-    preceded(pair(alt((tag("OK"), tag("PREAUTH"))), space), resp_text)(input)
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum GreetingStatus {
+    Ok,
+    // already authenticated, e.g. through a tunnel or local socket
+    PreAuth,
+    // the server rejects the connection
+    Bye,
+}
+fn resp_cond_auth(input: &str) -> IResult<&str, (GreetingStatus, ResponseText<'_>)> {
+    separated_pair(
+        alt((
+            map(tag("OK"), |_| GreetingStatus::Ok),
+            map(tag("PREAUTH"), |_| GreetingStatus::PreAuth),
+        )),
+        space,
+        resp_text,
+    )(input)
 }
 
-fn resp_cond_bye(input: &str) -> IResult<&str, ResponseText> {
+fn resp_cond_bye(input: &str) -> IResult<&str, ResponseText<'_>> {
     preceded(pair(tag("BYE"), space), resp_text)(input)
 }
 
-fn response_fatal(input: &str) -> IResult<&str, ResponseText> {
+fn response_fatal(input: &str) -> IResult<&str, ResponseText<'_>> {
     // Server closes connection immediately
-    delimited(tag("*"), resp_cond_bye, crlf)(input)
+    delimited(pair(tag("*"), space), resp_cond_bye, crlf)(input)
 }
 
 fn nil(input: &str) -> IResult<&str, &str> {
     tag("NIL")(input)
 }
 
-fn addr_adl(input: &str) -> IResult<&str, &str> {
+fn addr_adl(input: &str) -> IResult<&str, Option<&str>> {
     // Holds route from [RFC-2822] route-addr if non-NIL
     nstring(input)
 }
 
-fn addr_host(input: &str) -> IResult<&str, &str> {
+fn addr_host(input: &str) -> IResult<&str, Option<&str>> {
     // NIL indicates [RFC-2822] group syntax.
     // Otherwise, holds [RFC-2822] domain name
     nstring(input)
 }
 
-fn addr_mailbox(input: &str) -> IResult<&str, &str> {
+fn addr_mailbox(input: &str) -> IResult<&str, Option<&str>> {
     // NIL indicates end of [RFC-2822] group; if
     // non-NIL and addr-host is NIL, holds
     // [RFC-2822] group name.
@@ -521,19 +567,20 @@ fn addr_mailbox(input: &str) -> IResult<&str, &str> {
     nstring(input)
 }
 
-fn addr_name(input: &str) -> IResult<&str, &str> {
+fn addr_name(input: &str) -> IResult<&str, Option<&str>> {
     // If non-NIL, holds phrase from [RFC-2822]
     // mailbox after removing [RFC-2822] quoting
     nstring(input)
 }
 
-struct Address<'a> {
-    name: &'a str,
-    adl: &'a str,
-    mailbox: &'a str,
-    host: &'a str,
+#[derive(Debug, PartialEq)]
+pub struct Address<'a> {
+    pub name: Option<&'a str>,
+    pub adl: Option<&'a str>,
+    pub mailbox: Option<&'a str>,
+    pub host: Option<&'a str>,
 }
-fn address(input: &str) -> IResult<&str, Address> {
+fn address(input: &str) -> IResult<&str, Address<'_>> {
     map(
         delimited(
             char('('),
@@ -554,77 +601,67 @@ fn address(input: &str) -> IResult<&str, Address> {
     )(input)
 }
 
-fn env_bcc(input: &str) -> IResult<&str, Vec<Address>> {
+fn address_list(input: &str) -> IResult<&str, Vec<Address<'_>>> {
     alt((
         delimited(char('('), many1(address), char(')')),
         map(nil, |_| Vec::with_capacity(0)),
     ))(input)
 }
 
-fn env_cc(input: &str) -> IResult<&str, Vec<Address>> {
-    alt((
-        delimited(char('('), many1(address), char(')')),
-        map(nil, |_| Vec::with_capacity(0)),
-    ))(input)
+fn env_bcc(input: &str) -> IResult<&str, Vec<Address<'_>>> {
+    address_list(input)
 }
 
-fn env_date(input: &str) -> IResult<&str, &str> {
+fn env_cc(input: &str) -> IResult<&str, Vec<Address<'_>>> {
+    address_list(input)
+}
+
+fn env_date(input: &str) -> IResult<&str, Option<&str>> {
     nstring(input)
 }
 
-fn env_from(input: &str) -> IResult<&str, Vec<Address>> {
-    alt((
-        delimited(char('('), many1(address), char(')')),
-        map(nil, |_| Vec::with_capacity(0)),
-    ))(input)
+fn env_from(input: &str) -> IResult<&str, Vec<Address<'_>>> {
+    address_list(input)
 }
 
-fn env_in_reply_to(input: &str) -> IResult<&str, &str> {
+fn env_in_reply_to(input: &str) -> IResult<&str, Option<&str>> {
     nstring(input)
 }
 
-fn env_message_id(input: &str) -> IResult<&str, &str> {
+fn env_message_id(input: &str) -> IResult<&str, Option<&str>> {
     nstring(input)
 }
 
-fn env_reply_to(input: &str) -> IResult<&str, Vec<Address>> {
-    alt((
-        delimited(char('('), many1(address), char(')')),
-        map(nil, |_| Vec::with_capacity(0)),
-    ))(input)
+fn env_reply_to(input: &str) -> IResult<&str, Vec<Address<'_>>> {
+    address_list(input)
 }
 
-fn env_sender(input: &str) -> IResult<&str, Vec<Address>> {
-    alt((
-        delimited(char('('), many1(address), char(')')),
-        map(nil, |_| Vec::with_capacity(0)),
-    ))(input)
+fn env_sender(input: &str) -> IResult<&str, Vec<Address<'_>>> {
+    address_list(input)
 }
 
-fn env_subject(input: &str) -> IResult<&str, &str> {
+fn env_subject(input: &str) -> IResult<&str, Option<&str>> {
     nstring(input)
 }
 
-fn env_to(input: &str) -> IResult<&str, Vec<Address>> {
-    alt((
-        delimited(char('('), many1(address), char(')')),
-        map(nil, |_| Vec::with_capacity(0)),
-    ))(input)
+fn env_to(input: &str) -> IResult<&str, Vec<Address<'_>>> {
+    address_list(input)
 }
 
-struct Envelope<'a> {
-    date: &'a str,
-    subject: &'a str,
-    from: Vec<Address<'a>>,
-    sender: Vec<Address<'a>>,
-    reply_to: Vec<Address<'a>>,
-    to: Vec<Address<'a>>,
-    cc: Vec<Address<'a>>,
-    bcc: Vec<Address<'a>>,
-    in_reply_to: &'a str,
-    message_id: &'a str,
-}
-fn envelope(input: &str) -> IResult<&str, Envelope> {
+#[derive(Debug, PartialEq)]
+pub struct Envelope<'a> {
+    pub date: Option<&'a str>,
+    pub subject: Option<&'a str>,
+    pub from: Vec<Address<'a>>,
+    pub sender: Vec<Address<'a>>,
+    pub reply_to: Vec<Address<'a>>,
+    pub to: Vec<Address<'a>>,
+    pub cc: Vec<Address<'a>>,
+    pub bcc: Vec<Address<'a>>,
+    pub in_reply_to: Option<&'a str>,
+    pub message_id: Option<&'a str>,
+}
+fn envelope(input: &str) -> IResult<&str, Envelope<'_>> {
     map(
         delimited(
             char('('),
@@ -669,11 +706,11 @@ fn media_basic(input: &str) -> IResult<&str, (&str, &str)> {
             delimited(
                 char('"'),
                 alt((
-                    tag("APPLICATION"),
-                    tag("AUDIO"),
-                    tag("IMAGE"),
-                    tag("MESSAGE"),
-                    tag("VIDEO"),
+                    tag_no_case("APPLICATION"),
+                    tag_no_case("AUDIO"),
+                    tag_no_case("IMAGE"),
+                    tag_no_case("MESSAGE"),
+                    tag_no_case("VIDEO"),
                 )),
                 char('"'),
             ),
@@ -688,11 +725,11 @@ fn body_fld_octets(input: &str) -> IResult<&str, u32> {
     number(input)
 }
 
-fn body_fld_id(input: &str) -> IResult<&str, &str> {
+fn body_fld_id(input: &str) -> IResult<&str, Option<&str>> {
     nstring(input)
 }
 
-fn body_fld_desc(input: &str) -> IResult<&str, &str> {
+fn body_fld_desc(input: &str) -> IResult<&str, Option<&str>> {
     nstring(input)
 }
 
@@ -701,11 +738,11 @@ fn body_fld_enc(input: &str) -> IResult<&str, &str> {
         delimited(
             char('"'),
             alt((
-                tag("7BIT"),
-                tag("8BIT"),
-                tag("BINARY"),
-                tag("BASE64"),
-                tag("QUOTED-PRINTABLE"),
+                tag_no_case("7BIT"),
+                tag_no_case("8BIT"),
+                tag_no_case("BINARY"),
+                tag_no_case("BASE64"),
+                tag_no_case("QUOTED-PRINTABLE"),
             )),
             char('"'),
         ),
@@ -724,14 +761,15 @@ fn body_fld_param(input: &str) -> IResult<&str, Vec<(&str, &str)>> {
     ))(input)
 }
 
-struct BodyFields<'a> {
-    param: Vec<(&'a str, &'a str)>,
-    id: &'a str,
-    desc: &'a str,
-    enc: &'a str,
-    octets: u32,
-}
-fn body_fields(input: &str) -> IResult<&str, BodyFields> {
+#[derive(Debug, PartialEq)]
+pub struct BodyFields<'a> {
+    pub param: Vec<(&'a str, &'a str)>,
+    pub id: Option<&'a str>,
+    pub desc: Option<&'a str>,
+    pub enc: &'a str,
+    pub octets: u32,
+}
+fn body_fields(input: &str) -> IResult<&str, BodyFields<'_>> {
     map(
         tuple((
             body_fld_param,
@@ -750,9 +788,16 @@ fn body_fields(input: &str) -> IResult<&str, BodyFields> {
     )(input)
 }
 
-fn body_type_basic(input: &str) -> IResult<&str, ((&str, &str), BodyFields)> {
+fn body_type_basic(input: &str) -> IResult<&str, BodyType1Part<'_>> {
     // MESSAGE subtype MUST NOT be "RFC822"
-    separated_pair(media_basic, space, body_fields)(input)
+    map(
+        separated_pair(media_basic, space, body_fields),
+        |((media_type, media_subtype), body_fields)| BodyType1Part::Basic {
+            media_type,
+            media_subtype,
+            body_fields,
+        },
+    )(input)
 }
 
 fn body_fld_lines(input: &str) -> IResult<&str, u32> {
@@ -763,19 +808,18 @@ fn media_message(input: &str) -> IResult<&str, &str> {
     // Defined in [MIME-IMT]
     delimited(
         char('"'),
-        tag("MESSAGE"),
-        tuple((char('"'), space, char('"'), tag("RFC822"), char('"'))),
+        tag_no_case("MESSAGE"),
+        tuple((
+            char('"'),
+            space,
+            char('"'),
+            tag_no_case("RFC822"),
+            char('"'),
+        )),
     )(input)
 }
 
-struct BodyTypeMesage<'a> {
-    media_message: &'a str,
-    body_fields: BodyFields<'a>,
-    envelope: Envelope<'a>,
-    body: &'a str,
-    body_fld_lines: u32,
-}
-fn body_type_msg(input: &str) -> IResult<&str, BodyTypeMesage> {
+fn body_type_msg(input: &str) -> IResult<&str, BodyType1Part<'_>> {
     map(
         tuple((
             media_message,
@@ -784,11 +828,10 @@ fn body_type_msg(input: &str) -> IResult<&str, BodyTypeMesage> {
             preceded(space, body),
             preceded(space, body_fld_lines),
         )),
-        |(media_message, body_fields, envelope, body, body_fld_lines)| BodyTypeMesage {
-            media_message,
+        |(_, body_fields, envelope, body, body_fld_lines)| BodyType1Part::Message {
             body_fields,
-            envelope,
-            body,
+            envelope: Box::new(envelope),
+            body: Box::new(body),
             body_fld_lines,
         },
     )(input)
@@ -797,36 +840,36 @@ fn body_type_msg(input: &str) -> IResult<&str, BodyTypeMesage> {
 fn media_text(input: &str) -> IResult<&str, &str> {
     // Defined in [MIME-IMT]
     preceded(
-        tuple((char('"'), tag("TEXT"), char('"'), space)),
+        tuple((char('"'), tag_no_case("TEXT"), char('"'), space)),
         media_subtype,
     )(input)
 }
 
-struct BodyTypeText<'a> {
-    media_text: &'a str,
-    body_fields: BodyFields<'a>,
-    body_fld_lines: u32,
-}
-fn body_type_text(input: &str) -> IResult<&str, BodyTypeText> {
+fn body_type_text(input: &str) -> IResult<&str, BodyType1Part<'_>> {
     map(
         tuple((
             media_text,
             preceded(space, body_fields),
             preceded(space, body_fld_lines),
         )),
-        |(media_text, body_fields, body_fld_lines)| BodyTypeText {
-            media_text,
+        |(media_subtype, body_fields, body_fld_lines)| BodyType1Part::Text {
+            media_subtype,
             body_fields,
             body_fld_lines,
         },
     )(input)
 }
 
-fn body_fld_md5(input: &str) -> IResult<&str, &str> {
+fn body_fld_md5(input: &str) -> IResult<&str, Option<&str>> {
     nstring(input)
 }
 
-fn body_fld_dsp(input: &str) -> IResult<&str, Option<(&str, Vec<(&str, &str)>)>> {
+#[derive(Debug, PartialEq)]
+pub struct Disposition<'a> {
+    pub kind: &'a str,
+    pub param: Vec<(&'a str, &'a str)>,
+}
+fn body_fld_dsp(input: &str) -> IResult<&str, Option<Disposition<'_>>> {
     alt((
         map(
             delimited(
@@ -834,16 +877,49 @@ fn body_fld_dsp(input: &str) -> IResult<&str, Option<(&str, Vec<(&str, &str)>)>>
                 separated_pair(string, space, body_fld_param),
                 char(')'),
             ),
-            |res| Some(res),
+            |(kind, param)| Some(Disposition { kind, param }),
         ),
         map(nil, |_| None),
     ))(input)
 }
 
-fn body_ext_1part(input: &str) -> IResult<&str, &str> {
-    // MUST NOT be returned on non-extensible "BODY" fetch
-    pair(
-        body_fld_md5,
+fn body_fld_lang(input: &str) -> IResult<&str, Vec<&str>> {
+    alt((
+        map(nstring, |lang| lang.into_iter().collect()),
+        delimited(char('('), separated_list1(space, string), char(')')),
+    ))(input)
+}
+
+fn body_fld_loc(input: &str) -> IResult<&str, Option<&str>> {
+    nstring(input)
+}
+
+fn body_extension(input: &str) -> IResult<&str, ()> {
+    // Future expansion.  Client implementations
+    // MUST accept body-extension fields.  Server
+    // implementations MUST NOT generate
+    // body-extension fields except as defined by
+    // future standard or standards-track
+    // revisions of this specification.
+    alt((
+        map(nstring, |_| ()),
+        map(number, |_| ()),
+        map(
+            delimited(char('('), separated_list1(space, body_extension), char(')')),
+            |_| (),
+        ),
+    ))(input)
+}
+
+// extension data shared by body-ext-1part and body-ext-mpart
+#[derive(Debug, PartialEq, Default)]
+pub struct BodyExtension<'a> {
+    pub dsp: Option<Disposition<'a>>,
+    pub lang: Vec<&'a str>,
+    pub loc: Option<&'a str>,
+}
+fn body_ext_tail(input: &str) -> IResult<&str, BodyExtension<'_>> {
+    map(
         opt(preceded(
             space,
             pair(
@@ -854,23 +930,112 @@ fn body_ext_1part(input: &str) -> IResult<&str, &str> {
                         body_fld_lang,
                         opt(preceded(
                             space,
-                            pair(body_fld_loc, many0(preceded(space, body_extension))),
+                            terminated(body_fld_loc, many0(preceded(space, body_extension))),
                         )),
                     ),
                 )),
             ),
         )),
+        |tail| match tail {
+            None => BodyExtension::default(),
+            Some((dsp, None)) => BodyExtension {
+                dsp,
+                ..Default::default()
+            },
+            Some((dsp, Some((lang, loc)))) => BodyExtension {
+                dsp,
+                lang,
+                loc: loc.flatten(),
+            },
+        },
+    )(input)
+}
+
+#[derive(Debug, PartialEq)]
+pub struct BodyExtension1Part<'a> {
+    pub md5: Option<&'a str>,
+    pub extension: BodyExtension<'a>,
+}
+fn body_ext_1part(input: &str) -> IResult<&str, BodyExtension1Part<'_>> {
+    // MUST NOT be returned on non-extensible "BODY" fetch
+    map(pair(body_fld_md5, body_ext_tail), |(md5, extension)| {
+        BodyExtension1Part { md5, extension }
+    })(input)
+}
+
+#[derive(Debug, PartialEq)]
+pub struct BodyExtensionMPart<'a> {
+    pub param: Vec<(&'a str, &'a str)>,
+    pub extension: BodyExtension<'a>,
+}
+fn body_ext_mpart(input: &str) -> IResult<&str, BodyExtensionMPart<'_>> {
+    // MUST NOT be returned on non-extensible "BODY" fetch
+    map(pair(body_fld_param, body_ext_tail), |(param, extension)| {
+        BodyExtensionMPart { param, extension }
+    })(input)
+}
+
+#[derive(Debug, PartialEq)]
+pub enum BodyType1Part<'a> {
+    Basic {
+        media_type: &'a str,
+        media_subtype: &'a str,
+        body_fields: BodyFields<'a>,
+    },
+    Message {
+        body_fields: BodyFields<'a>,
+        envelope: Box<Envelope<'a>>,
+        body: Box<Body<'a>>,
+        body_fld_lines: u32,
+    },
+    Text {
+        media_subtype: &'a str,
+        body_fields: BodyFields<'a>,
+        body_fld_lines: u32,
+    },
+}
+fn body_type_1part(input: &str) -> IResult<&str, Body<'_>> {
+    // msg and text are tried first, because basic would also accept their media types
+    map(
+        pair(
+            alt((body_type_msg, body_type_text, body_type_basic)),
+            opt(preceded(space, body_ext_1part)),
+        ),
+        |(body_type, extension)| Body::SinglePart {
+            body_type,
+            extension,
+        },
     )(input)
 }
 
-fn body_type_1part(input: &str) -> IResult<&str, &str> {
-    pair(
-        alt((body_type_basic, body_type_msg, body_type_text)),
-        opt(preceded(space, body_ext_1part)),
+fn body_type_mpart(input: &str) -> IResult<&str, Body<'_>> {
+    map(
+        tuple((
+            many1(body),
+            preceded(space, media_subtype),
+            opt(preceded(space, body_ext_mpart)),
+        )),
+        |(bodies, media_subtype, extension)| Body::MultiPart {
+            bodies,
+            media_subtype,
+            extension,
+        },
     )(input)
 }
 
-fn body(input: &str) -> IResult<&str, &str> {
+#[derive(Debug, PartialEq)]
+pub enum Body<'a> {
+    SinglePart {
+        body_type: BodyType1Part<'a>,
+        extension: Option<BodyExtension1Part<'a>>,
+    },
+    MultiPart {
+        bodies: Vec<Body<'a>>,
+        media_subtype: &'a str,
+        extension: Option<BodyExtensionMPart<'a>>,
+    },
+}
+fn body(input: &str) -> IResult<&str, Body<'_>> {
     delimited(
         char('('),
         alt((body_type_1part, body_type_mpart)),
@@ -878,81 +1043,461 @@ fn body(input: &str) -> IResult<&str, &str> {
     )(input)
 }
 
-fn msg_att_static(input: &str) -> IResult<&str, Vec<Flag>> {
+#[derive(Debug, PartialEq)]
+pub enum MessageAttribute<'a> {
+    Envelope(Box<Envelope<'a>>),
+    InternalDate(DateTime<FixedOffset>),
+    Rfc822(Option<&'a str>),
+    Rfc822Header(Option<&'a str>),
+    Rfc822Text(Option<&'a str>),
+    Rfc822Size(u32),
+    BodyStructure(Body<'a>),
+    Body(Body<'a>),
+    BodySection {
+        section: Option<SectionSpec<'a>>,
+        origin: Option<u32>,
+        data: Option<&'a str>,
+    },
+    Uid(u32),
+    Flags(Vec<Flag<'a>>),
+    ModSeq(u64),
+    GmailMessageId(u64),
+    GmailThreadId(u64),
+    GmailLabels(Vec<&'a str>),
+}
+fn msg_att_static(input: &str) -> IResult<&str, MessageAttribute<'_>> {
     alt((
-        separated_pair(tag("ENVELOPE"), space, envelope),
-        separated_pair(tag("INTERNALDATE"), space, date_time),
-        separated_pair(tag("RFC822.TEXT"), space, nstring),
-        separated_pair(tag("RFC822.HEADER"), space, nstring),
-        separated_pair(tag("RFC822"), space, nstring),
-        separated_pair(tag("RFC822.SIZE"), space, number),
-        separated_pair(tag("BODYSTRUCTURE"), space, body),
-        separated_pair(tag("BODY"), space, body),
+        preceded(pair(tag("ENVELOPE"), space), envelope)
+            .map(|envelope| MessageAttribute::Envelope(Box::new(envelope))),
+        preceded(pair(tag("INTERNALDATE"), space), date_time).map(MessageAttribute::InternalDate),
+        preceded(pair(tag("RFC822.TEXT"), space), nstring).map(MessageAttribute::Rfc822Text),
+        preceded(pair(tag("RFC822.HEADER"), space), nstring).map(MessageAttribute::Rfc822Header),
+        preceded(pair(tag("RFC822.SIZE"), space), number).map(MessageAttribute::Rfc822Size),
+        preceded(pair(tag("RFC822"), space), nstring).map(MessageAttribute::Rfc822),
+        preceded(pair(tag("BODYSTRUCTURE"), space), body).map(MessageAttribute::BodyStructure),
+        preceded(pair(tag("BODY"), space), body).map(MessageAttribute::Body),
         separated_pair(
-            tuple((
+            preceded(
                 tag("BODY"),
-                section,
-                opt(delimited(char('<'), number, char('>'))),
-            )),
+                pair(section, opt(delimited(char('<'), number, char('>')))),
+            ),
             space,
             nstring,
+        )
+        .map(|((section, origin), data)| MessageAttribute::BodySection {
+            section,
+            origin,
+            data,
+        }),
+        preceded(pair(tag("UID"), space), uniqueid).map(MessageAttribute::Uid),
+    ))(input)
+}
+
+fn msg_att_dynamic(input: &str) -> IResult<&str, MessageAttribute<'_>> {
+    alt((
+        map(
+            separated_pair(
+                tag("FLAGS"),
+                space,
+                delimited(char('('), separated_list0(space, flag), char(')')),
+            ),
+            |(_, flags)| MessageAttribute::Flags(flags),
+        ),
+        // fetch-mod-resp as defined by https://datatracker.ietf.org/doc/html/rfc7162#section-7
+        map(
+            separated_pair(
+                tag("MODSEQ"),
+                space,
+                delimited(char('('), mod_sequence_value, char(')')),
+            ),
+            |(_, modseq)| MessageAttribute::ModSeq(modseq),
         ),
-        separated_pair(tag("UID"), space, uniqueid),
     ))(input)
 }
 
-fn msg_att_dynamic(input: &str) -> IResult<&str, Vec<Flag>> {
+fn gmail_label(input: &str) -> IResult<&str, &str> {
+    // system labels like \Inbox are sent as flag-like atoms
+    alt((recognize(preceded(char('\\'), atom)), astring))(input)
+}
+
+fn msg_att_gmail(input: &str) -> IResult<&str, MessageAttribute<'_>> {
+    // defined by https://developers.google.com/gmail/imap/imap-extensions
+    alt((
+        preceded(pair(tag("X-GM-MSGID"), space), number64).map(MessageAttribute::GmailMessageId),
+        preceded(pair(tag("X-GM-THRID"), space), number64).map(MessageAttribute::GmailThreadId),
+        preceded(
+            pair(tag("X-GM-LABELS"), space),
+            delimited(char('('), separated_list0(space, gmail_label), char(')')),
+        )
+        .map(MessageAttribute::GmailLabels),
+    ))(input)
+}
+
+fn msg_att(input: &str) -> IResult<&str, Vec<MessageAttribute<'_>>> {
+    delimited(
+        char('('),
+        separated_list1(space, alt((msg_att_dynamic, msg_att_static, msg_att_gmail))),
+        char(')'),
+    )(input)
+}
+
+#[derive(Debug, PartialEq)]
+pub enum MessageDataType<'a> {
+    Expunge,
+    Fetch(Vec<MessageAttribute<'a>>),
+}
+#[derive(Debug, PartialEq)]
+pub struct MessageData<'a> {
+    pub sequence_number: u32,
+    pub data: MessageDataType<'a>,
+}
+fn message_data(input: &str) -> IResult<&str, MessageData<'_>> {
     map(
         separated_pair(
-            tag("FLAGS"),
+            nz_number,
             space,
-            delimited(char('('), separated_list0(space, flag), char(')')),
+            alt((
+                map(tag("EXPUNGE"), |_| MessageDataType::Expunge),
+                map(separated_pair(tag("FETCH"), space, msg_att), |(_, attr)| {
+                    MessageDataType::Fetch(attr)
+                }),
+            )),
+        ),
+        |(sequence_number, data)| MessageData {
+            sequence_number,
+            data,
+        },
+    )(input)
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Vanished {
+    // reported for changes since the state passed to SELECT (QRESYNC), not as they happen
+    pub earlier: bool,
+    pub uids: Vec<RangeInclusive<u32>>,
+}
+fn vanished_response(input: &str) -> IResult<&str, Vanished> {
+    // defined by https://datatracker.ietf.org/doc/html/rfc7162#section-7
+    tuple((
+        tag("VANISHED"),
+        opt(preceded(space, tag("(EARLIER)"))),
+        space,
+        sequence_set,
+    ))
+    .map(|(_, earlier, _, uids)| Vanished {
+        earlier: earlier.is_some(),
+        uids,
+    })
+    .parse(input)
+}
+
+fn sequence_set(input: &str) -> IResult<&str, Vec<RangeInclusive<u32>>> {
+    // technically "*" is allowed as well, but servers don't send it in responses
+    separated_list1(
+        char(','),
+        map(
+            pair(nz_number, opt(preceded(char(':'), nz_number))),
+            |(start, end)| {
+                let end = end.unwrap_or(start);
+                start.min(end)..=start.max(end)
+            },
         ),
-        |(_, flags)| flags,
     )(input)
 }
 
-fn msg_att(input: &str) -> IResult<&str, &str> {
+#[derive(Debug, PartialEq, Default)]
+pub struct ESearch<'a> {
+    pub tag: Option<&'a str>,
+    pub uid: bool,
+    pub min: Option<u32>,
+    pub max: Option<u32>,
+    pub count: Option<u32>,
+    pub all: Vec<RangeInclusive<u32>>,
+}
+fn esearch_response(input: &str) -> IResult<&str, ESearch<'_>> {
+    // defined by https://datatracker.ietf.org/doc/html/rfc4731#section-3.1
+    enum ReturnData {
+        Min(u32),
+        Max(u32),
+        Count(u32),
+        All(Vec<RangeInclusive<u32>>),
+    }
+    map(
+        tuple((
+            tag("ESEARCH"),
+            opt(preceded(
+                space,
+                delimited(pair(tag("(TAG"), space), string, char(')')),
+            )),
+            opt(preceded(space, tag("UID"))),
+            many0(preceded(
+                space,
+                alt((
+                    preceded(pair(tag("MIN"), space), nz_number).map(ReturnData::Min),
+                    preceded(pair(tag("MAX"), space), nz_number).map(ReturnData::Max),
+                    preceded(pair(tag("COUNT"), space), number).map(ReturnData::Count),
+                    preceded(pair(tag("ALL"), space), sequence_set).map(ReturnData::All),
+                )),
+            )),
+        )),
+        |(_, tag, uid, return_data)| {
+            let mut esearch = ESearch {
+                tag,
+                uid: uid.is_some(),
+                ..Default::default()
+            };
+            for data in return_data {
+                match data {
+                    ReturnData::Min(min) => esearch.min = Some(min),
+                    ReturnData::Max(max) => esearch.max = Some(max),
+                    ReturnData::Count(count) => esearch.count = Some(count),
+                    ReturnData::All(all) => esearch.all = all,
+                }
+            }
+            esearch
+        },
+    )(input)
+}
+
+#[derive(Debug, PartialEq)]
+pub enum MailboxData<'a> {
+    Flags(Vec<Flag<'a>>),
+    Search(Vec<u32>),
+    ESearch(ESearch<'a>),
+    Exists(u32),
+    Recent(u32),
+    List(MailboxList<'a>),
+    Lsub(MailboxList<'a>),
+    Status(&'a str, Vec<StatusAttribute>),
+    Thread(Vec<Thread>),
+}
+fn mailbox_data(input: &str) -> IResult<&str, MailboxData<'_>> {
+    alt((
+        tuple((
+            tag("STATUS"),
+            space,
+            mailbox,
+            space,
+            delimited(
+                char('('),
+                separated_list0(space, status_attribute),
+                char(')'),
+            ),
+        ))
+        .map(|(_, _, name, _, attributes)| MailboxData::Status(name, attributes)),
+        preceded(pair(tag("LIST"), space), mailbox_list).map(MailboxData::List),
+        preceded(pair(tag("LSUB"), space), mailbox_list).map(MailboxData::Lsub),
+        preceded(tag("SEARCH"), many0(preceded(space, nz_number))).map(MailboxData::Search),
+        thread_data.map(MailboxData::Thread),
+        esearch_response.map(MailboxData::ESearch),
+        preceded(
+            pair(tag("FLAGS"), space),
+            delimited(char('('), separated_list0(space, flag), char(')')),
+        )
+        .map(MailboxData::Flags),
+        terminated(number, pair(space, tag("EXISTS"))).map(MailboxData::Exists),
+        terminated(number, pair(space, tag("RECENT"))).map(MailboxData::Recent),
+    ))(input)
+}
+
+// a message and its replies, defined by https://datatracker.ietf.org/doc/html/rfc5256#section-4
+#[derive(Debug, PartialEq)]
+pub struct Thread {
+    // missing for a parent that doesn't match the search, while some of its replies do
+    pub uid: Option<u32>,
+    pub children: Vec<Thread>,
+}
+fn thread_data(input: &str) -> IResult<&str, Vec<Thread>> {
+    preceded(tag("THREAD"), opt(preceded(space, many1(thread_list))))
+        .map(Option::unwrap_or_default)
+        .parse(input)
+}
+fn thread_list(input: &str) -> IResult<&str, Thread> {
     delimited(
         char('('),
-        separated_list1(space, alt((msg_att_dynamic, msg_att_static))),
+        alt((
+            pair(
+                separated_list1(space, nz_number),
+                opt(preceded(space, thread_nested)),
+            )
+            .map(|(uids, nested)| {
+                // each member is the only reply to the one before
+                let mut uids = uids.into_iter().rev();
+                let mut thread = Thread {
+                    uid: uids.next(),
+                    children: nested.unwrap_or_default(),
+                };
+                for uid in uids {
+                    thread = Thread {
+                        uid: Some(uid),
+                        children: vec![thread],
+                    };
+                }
+                thread
+            }),
+            thread_nested.map(|children| Thread {
+                uid: None,
+                children,
+            }),
+        )),
         char(')'),
     )(input)
 }
+fn thread_nested(input: &str) -> IResult<&str, Vec<Thread>> {
+    many1(thread_list)(input)
+}
 
-enum MessageDataType<'a> {
-    Expunge,
-    Fetch(&'a str),
+fn enable_data(input: &str) -> IResult<&str, Vec<Capability<'_>>> {
+    // defined by https://datatracker.ietf.org/doc/html/rfc5161#section-4
+    preceded(tag("ENABLED"), many0(preceded(space, capability)))(input)
 }
-fn message_data(input: &str) -> IResult<&str, (u32, MessageDataType)> {
-    separated_pair(
-        nz_number,
-        space,
-        alt((
-            map(tag("EXPUNGE"), |_| MessageDataType::Expunge),
-            map(separated_pair(tag("FETCH"), space, msg_att), |(_, attr)| {
-                MessageDataType::Fetch(attr)
-            }),
+
+fn mailbox(input: &str) -> IResult<&str, &str> {
+    // INBOX is case-insensitive.  All case variants of
+    // INBOX (e.g., "iNbOx") MUST be interpreted as INBOX
+    // not as an astring.
+    // only the whole name, INBOX.Sent is not INBOX
+    map(astring, |name| {
+        if name.eq_ignore_ascii_case("INBOX") {
+            "INBOX"
+        } else {
+            name
+        }
+    })(input)
+}
+
+#[derive(Debug, PartialEq)]
+pub enum StatusAttribute {
+    Messages(u32),
+    Recent(u32),
+    UidNext(u32),
+    UidValidity(u32),
+    Unseen(u32),
+    // https://datatracker.ietf.org/doc/html/rfc7162#section-3.1.6
+    HighestModSeq(u64),
+}
+fn status_attribute(input: &str) -> IResult<&str, StatusAttribute> {
+    alt((
+        preceded(pair(tag("MESSAGES"), space), number).map(StatusAttribute::Messages),
+        preceded(pair(tag("RECENT"), space), number).map(StatusAttribute::Recent),
+        preceded(pair(tag("UIDNEXT"), space), nz_number).map(StatusAttribute::UidNext),
+        preceded(pair(tag("UIDVALIDITY"), space), nz_number).map(StatusAttribute::UidValidity),
+        preceded(pair(tag("UNSEEN"), space), number).map(StatusAttribute::Unseen),
+        preceded(pair(tag("HIGHESTMODSEQ"), space), mod_sequence_value)
+            .map(StatusAttribute::HighestModSeq),
+    ))(input)
+}
+
+#[derive(Debug, PartialEq)]
+pub struct MailboxList<'a> {
+    // e.g. \Noselect or \HasChildren
+    pub attributes: Vec<&'a str>,
+    // NIL for a flat hierarchy
+    pub delimiter: Option<char>,
+    pub name: &'a str,
+}
+fn mailbox_list(input: &str) -> IResult<&str, MailboxList<'_>> {
+    map(
+        tuple((
+            delimited(
+                char('('),
+                separated_list0(space, recognize(preceded(char('\\'), atom))),
+                char(')'),
+            ),
+            space,
+            alt((
+                map(
+                    delimited(
+                        char('"'),
+                        alt((
+                            preceded(char('\\'), one_of(QUOTED_SPECIALS)),
+                            none_of("\"\\"),
+                        )),
+                        char('"'),
+                    ),
+                    Some,
+                ),
+                map(nil, |_| None),
+            )),
+            space,
+            mailbox,
         )),
+        |(attributes, _, delimiter, _, name)| MailboxList {
+            attributes,
+            delimiter,
+            name,
+        },
+    )(input)
+}
+
+#[derive(Debug, PartialEq)]
+pub struct QuotaResource<'a> {
+    pub name: &'a str,
+    pub usage: u64,
+    pub limit: u64,
+}
+fn quota_resource(input: &str) -> IResult<&str, QuotaResource<'_>> {
+    map(
+        tuple((atom, preceded(space, number64), preceded(space, number64))),
+        |(name, usage, limit)| QuotaResource { name, usage, limit },
+    )(input)
+}
+
+#[derive(Debug, PartialEq)]
+pub struct Quota<'a> {
+    pub root: &'a str,
+    pub resources: Vec<QuotaResource<'a>>,
+}
+fn quota_response(input: &str) -> IResult<&str, Quota<'_>> {
+    // defined by https://datatracker.ietf.org/doc/html/rfc2087#section-5.1
+    map(
+        preceded(
+            pair(tag("QUOTA"), space),
+            separated_pair(
+                astring,
+                space,
+                delimited(char('('), separated_list0(space, quota_resource), char(')')),
+            ),
+        ),
+        |(root, resources)| Quota { root, resources },
+    )(input)
+}
+
+#[derive(Debug, PartialEq)]
+pub struct QuotaRoot<'a> {
+    pub mailbox: &'a str,
+    pub roots: Vec<&'a str>,
+}
+fn quotaroot_response(input: &str) -> IResult<&str, QuotaRoot<'_>> {
+    // defined by https://datatracker.ietf.org/doc/html/rfc2087#section-5.2
+    map(
+        preceded(
+            pair(tag("QUOTAROOT"), space),
+            pair(mailbox, many0(preceded(space, astring))),
+        ),
+        |(mailbox, roots)| QuotaRoot { mailbox, roots },
     )(input)
 }
 
 #[derive(Debug, PartialEq)]
 pub struct TaggedResponse<'a> {
     tag: Tag<'a>,
-    state: ResponseCondState<'a>,
+    pub state: ResponseCondState<'a>,
 }
-fn response_tagged(input: &str) -> IResult<&str, TaggedResponse> {
+fn response_tagged(input: &str) -> IResult<&str, TaggedResponse<'_>> {
     map(
         terminated(separated_pair(imap_tag, space, resp_cond_state), crlf),
         |(tag, state)| TaggedResponse { tag, state },
     )(input)
 }
 
-pub fn greeting(input: &str) -> IResult<&str, ResponseText> {
+pub fn greeting(input: &str) -> IResult<&str, (GreetingStatus, ResponseText<'_>)> {
     delimited(
         pair(tag("*"), space),
-        alt((resp_cond_auth, resp_cond_bye)),
+        alt((
+            resp_cond_auth,
+            map(resp_cond_bye, |text| (GreetingStatus::Bye, text)),
+        )),
         crlf,
     )(input)
 }
@@ -962,23 +1507,36 @@ pub enum ResponseLine<'a> {
     CapabilityData(Vec<Capability<'a>>),
     CondBye(ResponseText<'a>),
     CondState(ResponseCondState<'a>),
+    MailboxData(MailboxData<'a>),
+    MessageData(MessageData<'a>),
+    Enabled(Vec<Capability<'a>>),
+    Quota(Quota<'a>),
+    QuotaRoot(QuotaRoot<'a>),
+    Vanished(Vanished),
     Tagged(TaggedResponse<'a>),
     Fatal(ResponseText<'a>),
 }
-pub fn response_done(input: &str) -> IResult<&str, ResponseLine> {
+pub fn response_done(input: &str) -> IResult<&str, ResponseLine<'_>> {
     alt((
         map(response_tagged, ResponseLine::Tagged),
         map(response_fatal, ResponseLine::Fatal),
     ))(input)
 }
 
-pub fn response_data(input: &str) -> IResult<&str, ResponseLine> {
+pub fn response_data(input: &str) -> IResult<&str, ResponseLine<'_>> {
     delimited(
         pair(tag("*"), space),
         alt((
             map(resp_cond_state, ResponseLine::CondState),
             map(resp_cond_bye, ResponseLine::CondBye),
             map(capability_data, ResponseLine::CapabilityData),
+            map(mailbox_data, ResponseLine::MailboxData),
+            map(message_data, ResponseLine::MessageData),
+            map(enable_data, ResponseLine::Enabled),
+            // QUOTAROOT has to be tried first, because QUOTA is a prefix of it
+            map(quotaroot_response, ResponseLine::QuotaRoot),
+            map(quota_response, ResponseLine::Quota),
+            map(vanished_response, ResponseLine::Vanished),
         )),
         crlf,
     )(input)
@@ -988,6 +1546,7 @@ pub fn response_data(input: &str) -> IResult<&str, ResponseLine> {
 pub enum Capability<'a> {
     AuthType(&'a str),
     Custom(&'a str),
+    LoginDisabled,
     // technically not a capability as defined in bakus-naur, but easier to type this way
     Revision(Revision),
 }
@@ -1000,7 +1559,9 @@ mod tests {
 
     #[test]
     fn parse_ok_greeting() {
-        let (rest, response) = greeting(OK_GREETING).expect("response should be parseable");
+        let (rest, (status, response)) =
+            greeting(OK_GREETING).expect("response should be parseable");
+        assert_eq!(status, GreetingStatus::Ok);
         assert_eq!(
             response,
             ResponseText {
@@ -1019,4 +1580,258 @@ mod tests {
         );
         assert_eq!(rest, "")
     }
+
+    #[test]
+    fn distinguish_greeting_statuses() {
+        let (_, (status, _)) =
+            greeting("* PREAUTH IMAP4rev1 ready\r\n").expect("response should be parseable");
+        assert_eq!(status, GreetingStatus::PreAuth);
+        let (_, (status, text)) =
+            greeting("* BYE too many connections\r\n").expect("response should be parseable");
+        assert_eq!(status, GreetingStatus::Bye);
+        assert_eq!(text.text, "too many connections");
+    }
+
+    #[test]
+    fn keep_status_of_failed_commands() {
+        let (_, response) = response_done("abcd NO [TRYCREATE] no such mailbox\r\n")
+            .expect("response should be parseable");
+        let ResponseLine::Tagged(TaggedResponse { state, .. }) = response else {
+            panic!("response should be tagged");
+        };
+        assert_eq!(state.status, Status::No);
+        let (_, response) =
+            response_done("* BYE shutting down\r\n").expect("response should be parseable");
+        assert!(matches!(response, ResponseLine::Fatal(_)));
+    }
+
+    #[test]
+    fn parse_listed_mailboxes() {
+        let (rest, response) = response_data("* LIST (\\HasNoChildren) \".\" INBOX.Sent\r\n")
+            .expect("response should be parseable");
+        assert_eq!(
+            response,
+            ResponseLine::MailboxData(MailboxData::List(MailboxList {
+                attributes: vec!["\\HasNoChildren"],
+                delimiter: Some('.'),
+                name: "INBOX.Sent"
+            }))
+        );
+        assert_eq!(rest, "");
+        let (_, response) =
+            response_data("* LSUB () NIL inbox\r\n").expect("response should be parseable");
+        assert_eq!(
+            response,
+            ResponseLine::MailboxData(MailboxData::Lsub(MailboxList {
+                attributes: vec![],
+                delimiter: None,
+                name: "INBOX"
+            }))
+        );
+        let (_, response) = response_data("* LIST (\\Noselect) \"\\\\\" \"Work\\\\Old\"\r\n")
+            .expect("response should be parseable");
+        assert!(matches!(
+            response,
+            ResponseLine::MailboxData(MailboxData::List(MailboxList {
+                delimiter: Some('\\'),
+                ..
+            }))
+        ));
+    }
+
+    #[test]
+    fn parse_permanent_flags() {
+        let (rest, response) =
+            response_data("* OK [PERMANENTFLAGS (\\Deleted \\Seen \\*)] Limited\r\n")
+                .expect("response should be parseable");
+        assert_eq!(
+            response,
+            ResponseLine::CondState(ResponseCondState {
+                status: Status::Ok,
+                text: ResponseText {
+                    code: Some(ResponseTextCode::PermanentFlags(vec![
+                        Flag::Deleted,
+                        Flag::Seen,
+                        Flag::Wildcard
+                    ])),
+                    text: "Limited"
+                }
+            })
+        );
+        assert_eq!(rest, "")
+    }
+
+    #[test]
+    fn parse_enabled_capabilities() {
+        let (rest, response) =
+            response_data("* ENABLED UTF8=ACCEPT\r\n").expect("response should be parseable");
+        assert_eq!(
+            response,
+            ResponseLine::Enabled(vec![Capability::Custom("UTF8=ACCEPT")])
+        );
+        assert_eq!(rest, "")
+    }
+
+    #[test]
+    fn parse_select_untagged_responses() {
+        let responses = [
+            (
+                "* FLAGS (\\Answered \\Flagged \\Deleted \\Seen \\Draft)\r\n",
+                ResponseLine::MailboxData(MailboxData::Flags(vec![
+                    Flag::Answered,
+                    Flag::Flagged,
+                    Flag::Deleted,
+                    Flag::Seen,
+                    Flag::Draft,
+                ])),
+            ),
+            (
+                "* 172 EXISTS\r\n",
+                ResponseLine::MailboxData(MailboxData::Exists(172)),
+            ),
+            (
+                "* 1 RECENT\r\n",
+                ResponseLine::MailboxData(MailboxData::Recent(1)),
+            ),
+            (
+                "* 44 EXPUNGE\r\n",
+                ResponseLine::MessageData(MessageData {
+                    sequence_number: 44,
+                    data: MessageDataType::Expunge,
+                }),
+            ),
+        ];
+        for (input, expected) in responses {
+            let (rest, response) = response_data(input).expect("response should be parseable");
+            assert_eq!(response, expected);
+            assert_eq!(rest, "")
+        }
+    }
+
+    #[test]
+    fn parse_search_results() {
+        let (_, response) =
+            response_data("* SEARCH 2 84 882\r\n").expect("response should be parseable");
+        assert_eq!(
+            response,
+            ResponseLine::MailboxData(MailboxData::Search(vec![2, 84, 882]))
+        );
+        let (_, response) = response_data("* SEARCH\r\n").expect("response should be parseable");
+        assert_eq!(
+            response,
+            ResponseLine::MailboxData(MailboxData::Search(vec![]))
+        );
+        let (_, response) = response_data(
+            "* ESEARCH (TAG \"A285\") UID MIN 7 MAX 3800 COUNT 4 ALL 7,3000:3002\r\n",
+        )
+        .expect("response should be parseable");
+        assert_eq!(
+            response,
+            ResponseLine::MailboxData(MailboxData::ESearch(ESearch {
+                tag: Some("A285"),
+                uid: true,
+                min: Some(7),
+                max: Some(3800),
+                count: Some(4),
+                all: vec![7..=7, 3000..=3002],
+            }))
+        );
+    }
+
+    #[test]
+    fn parse_long_response_text_with_brackets() {
+        let text = format!(
+            "Fetch completed [{}] (0.001 + 0.000 + 0.002 secs).",
+            "padding ".repeat(25)
+        );
+        let line = format!("a0003 OK [READ-WRITE] {text}\r\n");
+        let (rest, response) = response_done(&line).expect("response should be parseable");
+        assert_eq!(rest, "");
+        assert_eq!(
+            response,
+            ResponseLine::Tagged(TaggedResponse {
+                tag: Tag("a0003"),
+                state: ResponseCondState {
+                    status: Status::Ok,
+                    text: ResponseText {
+                        code: Some(ResponseTextCode::ReadWrite),
+                        text: &text,
+                    },
+                },
+            })
+        );
+    }
+
+    #[test]
+    fn parse_response_code_without_text() {
+        let (_, response) =
+            response_data("* OK [UIDNEXT 4392]\r\n").expect("response should be parseable");
+        assert_eq!(
+            response,
+            ResponseLine::CondState(ResponseCondState {
+                status: Status::Ok,
+                text: ResponseText {
+                    code: Some(ResponseTextCode::UidNext(4392)),
+                    text: "",
+                },
+            })
+        );
+    }
+
+    #[test]
+    fn parse_thread_data() {
+        let (_, response) = response_data("* THREAD (2)(3 6 (4 23)(44 7 96))((11)(12))\r\n")
+            .expect("response should be parseable");
+        let thread = |uid, children| Thread {
+            uid: Some(uid),
+            children,
+        };
+        assert_eq!(
+            response,
+            ResponseLine::MailboxData(MailboxData::Thread(vec![
+                thread(2, vec![]),
+                thread(
+                    3,
+                    vec![thread(
+                        6,
+                        vec![
+                            thread(4, vec![thread(23, vec![])]),
+                            thread(44, vec![thread(7, vec![thread(96, vec![])])]),
+                        ]
+                    )]
+                ),
+                Thread {
+                    uid: None,
+                    children: vec![thread(11, vec![]), thread(12, vec![])],
+                },
+            ]))
+        );
+        let (_, response) = response_data("* THREAD\r\n").expect("response should be parseable");
+        assert_eq!(
+            response,
+            ResponseLine::MailboxData(MailboxData::Thread(vec![]))
+        );
+    }
+
+    #[test]
+    fn parse_vanished_response() {
+        let (_, response) = response_data("* VANISHED (EARLIER) 41,43:116,118\r\n")
+            .expect("response should be parseable");
+        assert_eq!(
+            response,
+            ResponseLine::Vanished(Vanished {
+                earlier: true,
+                uids: vec![41..=41, 43..=116, 118..=118],
+            })
+        );
+        let (_, response) =
+            response_data("* VANISHED 405\r\n").expect("response should be parseable");
+        assert_eq!(
+            response,
+            ResponseLine::Vanished(Vanished {
+                earlier: false,
+                uids: vec![405..=405],
+            })
+        );
+    }
 }
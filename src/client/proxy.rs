@@ -0,0 +1,122 @@
+use base64::{engine::general_purpose::STANDARD, Engine};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+};
+use tokio_socks::tcp::Socks5Stream;
+
+use crate::config::{Proxy, ProxyScheme};
+
+// the mail host is passed to the proxy by name, so it is resolved on the proxy's side
+pub async fn connect(proxy: &Proxy, host: &str, port: u16) -> TcpStream {
+    match proxy.scheme {
+        ProxyScheme::Socks5 => {
+            let proxy_address = (proxy.host.as_str(), proxy.port);
+            let stream = match (&proxy.user, &proxy.password) {
+                (Some(user), Some(password)) => {
+                    Socks5Stream::connect_with_password(proxy_address, (host, port), user, password)
+                        .await
+                }
+                _ => Socks5Stream::connect(proxy_address, (host, port)).await,
+            };
+            stream
+                .expect("connection through socks5 proxy should succeed")
+                .into_inner()
+        }
+        ProxyScheme::Http => http_connect(proxy, host, port).await,
+    }
+}
+
+async fn http_connect(proxy: &Proxy, host: &str, port: u16) -> TcpStream {
+    let mut stream = (TcpStream::connect((proxy.host.as_str(), proxy.port)).await)
+        .expect("connection to proxy should succeed");
+
+    let authority = if host.contains(':') {
+        format!("[{host}]:{port}")
+    } else {
+        format!("{host}:{port}")
+    };
+    let mut request = format!("CONNECT {authority} HTTP/1.1\r\nHost: {authority}\r\n");
+    if let (Some(user), Some(password)) = (&proxy.user, &proxy.password) {
+        let credentials = STANDARD.encode(format!("{user}:{password}"));
+        request.push_str(&format!("Proxy-Authorization: Basic {credentials}\r\n"));
+    }
+    request.push_str("\r\n");
+    (stream.write_all(request.as_bytes()).await).expect("sending CONNECT to proxy should succeed");
+
+    // read byte by byte so nothing after the headers is consumed before the tls handshake
+    let mut response = Vec::new();
+    while !response.ends_with(b"\r\n\r\n") {
+        let byte = (stream.read_u8().await).expect("proxy response should be readable");
+        response.push(byte);
+    }
+    let response = String::from_utf8_lossy(&response);
+    let status_line = response.lines().next().unwrap_or_default();
+    assert!(
+        status_line
+            .split(' ')
+            .nth(1)
+            .is_some_and(|status| status.starts_with('2')),
+        "proxy should accept CONNECT, but answered {status_line}"
+    );
+
+    stream
+}
+
+#[cfg(test)]
+mod tests {
+    use tokio::{io::AsyncBufReadExt, io::BufReader, net::TcpListener};
+
+    use super::*;
+
+    #[tokio::test]
+    async fn tunnel_through_http_connect() {
+        let listener = TcpListener::bind("127.0.0.1:0")
+            .await
+            .expect("listener should bind");
+        let port = listener
+            .local_addr()
+            .expect("listener should have an address")
+            .port();
+        let proxy = tokio::spawn(async move {
+            let (stream, _) = listener.accept().await.expect("client should connect");
+            let mut stream = BufReader::new(stream);
+            let mut request = String::new();
+            while !request.ends_with("\r\n\r\n") {
+                stream
+                    .read_line(&mut request)
+                    .await
+                    .expect("request should be readable");
+            }
+            stream
+                .write_all(b"HTTP/1.1 200 Connection established\r\n\r\n* OK ready\r\n")
+                .await
+                .expect("response should be writable");
+            request
+        });
+
+        let mut stream = connect(
+            &Proxy {
+                scheme: ProxyScheme::Http,
+                host: "127.0.0.1".to_string(),
+                port,
+                user: Some("me".to_string()),
+                password: Some("secret".to_string()),
+            },
+            "imap.example.org",
+            993,
+        )
+        .await;
+        let mut greeting = String::new();
+        stream
+            .read_to_string(&mut greeting)
+            .await
+            .expect("tunneled data should be readable");
+
+        assert_eq!(greeting, "* OK ready\r\n");
+        assert_eq!(
+            proxy.await.expect("proxy should not panic"),
+            "CONNECT imap.example.org:993 HTTP/1.1\r\nHost: imap.example.org:993\r\nProxy-Authorization: Basic bWU6c2VjcmV0\r\n\r\n"
+        );
+    }
+}
@@ -0,0 +1,288 @@
+use base64::{engine::general_purpose::STANDARD, Engine};
+use hmac::{Hmac, Mac};
+use md5::Md5;
+use rand::{distributions::Alphanumeric, Rng};
+use sha2::{Digest, Sha256};
+use subtle::ConstantTimeEq;
+
+use super::parser::Capability;
+
+// ordered from weakest to strongest
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
+pub enum Mechanism {
+    Plain,
+    CramMd5,
+    ScramSha256,
+}
+
+impl Mechanism {
+    pub fn name(self) -> &'static str {
+        match self {
+            Mechanism::Plain => "PLAIN",
+            Mechanism::CramMd5 => "CRAM-MD5",
+            Mechanism::ScramSha256 => "SCRAM-SHA-256",
+        }
+    }
+
+    pub fn strongest(capabilities: &[Capability]) -> Option<Self> {
+        let plaintext_allowed = plaintext_allowed(capabilities);
+        capabilities
+            .iter()
+            .filter_map(|capability| {
+                if let Capability::AuthType(auth_type) = capability {
+                    [Mechanism::Plain, Mechanism::CramMd5, Mechanism::ScramSha256]
+                        .into_iter()
+                        .find(|mechanism| mechanism.name().eq_ignore_ascii_case(auth_type))
+                } else {
+                    None
+                }
+            })
+            .filter(|mechanism| *mechanism != Mechanism::Plain || plaintext_allowed)
+            .max()
+    }
+}
+
+// servers advertise LOGINDISABLED when plaintext credentials must not be sent, e.g. before
+// STARTTLS, so neither LOGIN nor AUTHENTICATE PLAIN may be used
+pub fn plaintext_allowed(capabilities: &[Capability]) -> bool {
+    !capabilities.contains(&Capability::LoginDisabled)
+}
+
+// answers the challenges of one AUTHENTICATE exchange, PLAIN is left to LOGIN
+pub enum Exchange {
+    CramMd5 {
+        user: String,
+        password: String,
+    },
+    ScramSha256 {
+        scram: ScramSha256,
+        challenges: usize,
+    },
+}
+
+impl Exchange {
+    pub fn new(mechanism: Mechanism, user: &str, password: &str) -> Option<Self> {
+        match mechanism {
+            Mechanism::Plain => None,
+            Mechanism::CramMd5 => Some(Exchange::CramMd5 {
+                user: user.to_string(),
+                password: password.to_string(),
+            }),
+            Mechanism::ScramSha256 => Some(Exchange::ScramSha256 {
+                scram: ScramSha256::new(user, password),
+                challenges: 0,
+            }),
+        }
+    }
+
+    pub fn mechanism(&self) -> Mechanism {
+        match self {
+            Exchange::CramMd5 { .. } => Mechanism::CramMd5,
+            Exchange::ScramSha256 { .. } => Mechanism::ScramSha256,
+        }
+    }
+
+    // challenge and response are base64 encoded as sent over the wire
+    pub fn respond(&mut self, challenge: &str) -> Result<String, ()> {
+        match self {
+            Exchange::CramMd5 { user, password } => cram_md5(user, password, challenge),
+            Exchange::ScramSha256 { scram, challenges } => {
+                *challenges += 1;
+                match challenges {
+                    // the server starts with an empty challenge, as SASL-IR isn't used
+                    1 => Ok(scram.client_first()),
+                    2 => scram.client_final(challenge),
+                    _ => scram.verify_server_final(challenge).map(|()| String::new()),
+                }
+            }
+        }
+    }
+
+    // whether the server proved that it knows the password, which only SCRAM requires of it
+    pub fn verified(&self) -> bool {
+        match self {
+            Exchange::CramMd5 { .. } => true,
+            Exchange::ScramSha256 { challenges, .. } => *challenges >= 3,
+        }
+    }
+}
+
+// https://datatracker.ietf.org/doc/html/rfc2195
+// challenge and response are base64 encoded as sent over the wire
+pub fn cram_md5(user: &str, password: &str, challenge: &str) -> Result<String, ()> {
+    let challenge = STANDARD.decode(challenge).map_err(|_| ())?;
+    let mut mac =
+        Hmac::<Md5>::new_from_slice(password.as_bytes()).expect("hmac should accept any key");
+    mac.update(&challenge);
+    let digest: String = mac
+        .finalize()
+        .into_bytes()
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect();
+
+    Ok(STANDARD.encode(format!("{user} {digest}")))
+}
+
+// https://datatracker.ietf.org/doc/html/rfc5802 without channel binding
+// technically the password should be prepared with SASLprep, which is skipped here
+pub struct ScramSha256 {
+    password: String,
+    client_nonce: String,
+    client_first_bare: String,
+    server_signature: Option<Vec<u8>>,
+}
+
+impl ScramSha256 {
+    pub fn new(user: &str, password: &str) -> Self {
+        let client_nonce = rand::thread_rng()
+            .sample_iter(&Alphanumeric)
+            .take(24)
+            .map(char::from)
+            .collect();
+        Self::with_nonce(user, password, client_nonce)
+    }
+
+    pub(super) fn with_nonce(user: &str, password: &str, client_nonce: String) -> Self {
+        let user = user.replace('=', "=3D").replace(',', "=2C");
+        let client_first_bare = format!("n={user},r={client_nonce}");
+        Self {
+            password: password.to_string(),
+            client_nonce,
+            client_first_bare,
+            server_signature: None,
+        }
+    }
+
+    pub fn client_first(&self) -> String {
+        STANDARD.encode(format!("n,,{}", self.client_first_bare))
+    }
+
+    pub fn client_final(&mut self, server_first: &str) -> Result<String, ()> {
+        let server_first =
+            String::from_utf8(STANDARD.decode(server_first).map_err(|_| ())?).map_err(|_| ())?;
+        let mut nonce = None;
+        let mut salt = None;
+        let mut iterations = None;
+        for attribute in server_first.split(',') {
+            match attribute.split_once('=') {
+                Some(("r", value)) => nonce = Some(value),
+                Some(("s", value)) => salt = Some(STANDARD.decode(value).map_err(|_| ())?),
+                Some(("i", value)) => iterations = Some(value.parse::<u32>().map_err(|_| ())?),
+                _ => {}
+            }
+        }
+        let (nonce, salt, iterations) = (nonce.ok_or(())?, salt.ok_or(())?, iterations.ok_or(())?);
+        if !nonce.starts_with(&self.client_nonce) {
+            return Err(());
+        }
+
+        let mut salted_password = [0u8; 32];
+        pbkdf2::pbkdf2_hmac::<Sha256>(
+            self.password.as_bytes(),
+            &salt,
+            iterations,
+            &mut salted_password,
+        );
+        let client_key = hmac_sha256(&salted_password, b"Client Key");
+        let stored_key = Sha256::digest(&client_key);
+        let client_final_without_proof = format!("c=biws,r={nonce}");
+        let auth_message = format!(
+            "{},{server_first},{client_final_without_proof}",
+            self.client_first_bare
+        );
+        let client_signature = hmac_sha256(&stored_key, auth_message.as_bytes());
+        let client_proof: Vec<u8> = client_key
+            .iter()
+            .zip(client_signature)
+            .map(|(key, signature)| key ^ signature)
+            .collect();
+        let server_key = hmac_sha256(&salted_password, b"Server Key");
+        self.server_signature = Some(hmac_sha256(&server_key, auth_message.as_bytes()));
+
+        Ok(STANDARD.encode(format!(
+            "{client_final_without_proof},p={}",
+            STANDARD.encode(client_proof)
+        )))
+    }
+
+    pub fn verify_server_final(&self, server_final: &str) -> Result<(), ()> {
+        let server_final =
+            String::from_utf8(STANDARD.decode(server_final).map_err(|_| ())?).map_err(|_| ())?;
+        let verifier = server_final.strip_prefix("v=").ok_or(())?;
+        let verifier = STANDARD.decode(verifier).map_err(|_| ())?;
+        let expected = self.server_signature.as_ref().ok_or(())?;
+        if bool::from(expected.ct_eq(&verifier)) {
+            Ok(())
+        } else {
+            Err(())
+        }
+    }
+}
+
+fn hmac_sha256(key: &[u8], message: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("hmac should accept any key");
+    mac.update(message);
+    mac.finalize().into_bytes().to_vec()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn pick_strongest_advertised_mechanism() {
+        let capabilities = [
+            Capability::Custom("IDLE"),
+            Capability::AuthType("PLAIN"),
+            Capability::AuthType("SCRAM-SHA-256"),
+            Capability::AuthType("CRAM-MD5"),
+        ];
+        assert_eq!(
+            Mechanism::strongest(&capabilities),
+            Some(Mechanism::ScramSha256)
+        );
+        assert_eq!(Mechanism::strongest(&[Capability::Custom("IDLE")]), None);
+    }
+
+    #[test]
+    fn refuse_plaintext_when_login_is_disabled() {
+        let capabilities = [Capability::LoginDisabled, Capability::AuthType("PLAIN")];
+        assert!(!plaintext_allowed(&capabilities));
+        assert_eq!(Mechanism::strongest(&capabilities), None);
+    }
+
+    #[test]
+    fn answer_cram_md5_challenge() {
+        // example from https://datatracker.ietf.org/doc/html/rfc2195#section-2
+        let challenge = STANDARD.encode("<1896.697170952@postoffice.reston.mci.net>");
+        let response =
+            cram_md5("tim", "tanstaaftanstaaf", &challenge).expect("challenge should be valid");
+        assert_eq!(
+            STANDARD.decode(response).unwrap(),
+            b"tim b913a602c7eda7a495b4e6e7334d3890"
+        );
+    }
+
+    #[test]
+    fn authenticate_with_scram_sha_256() {
+        // example from https://datatracker.ietf.org/doc/html/rfc7677#section-3
+        let mut scram = ScramSha256::with_nonce("user", "pencil", "rOprNGfwEbeRWgbNEkqO".into());
+        assert_eq!(
+            STANDARD.decode(scram.client_first()).unwrap(),
+            b"n,,n=user,r=rOprNGfwEbeRWgbNEkqO"
+        );
+        let server_first = STANDARD.encode("r=rOprNGfwEbeRWgbNEkqO%hvYDpWUa2RaTCAfuxFIlj)hNlF$k0,s=W22ZaJ0SNY7soEsUEjb6gQ==,i=4096");
+        let client_final = scram
+            .client_final(&server_first)
+            .expect("server first message should be valid");
+        assert_eq!(
+            STANDARD.decode(client_final).unwrap(),
+            b"c=biws,r=rOprNGfwEbeRWgbNEkqO%hvYDpWUa2RaTCAfuxFIlj)hNlF$k0,p=dHzbZapWIk4jUhN+Ute9ytag9zjfMHgsqmmiz7AndVQ="
+        );
+        let server_final = STANDARD.encode("v=6rriTRBi23WpRR/wtup+mMhUZUn/dB5nLTJRsjl95G4=");
+        assert_eq!(scram.verify_server_final(&server_final), Ok(()));
+        let forged = STANDARD.encode("v=AAAATRBi23WpRR/wtup+mMhUZUn/dB5nLTJRsjl95G4=");
+        assert_eq!(scram.verify_server_final(&forged), Err(()));
+    }
+}
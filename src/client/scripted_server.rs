@@ -0,0 +1,37 @@
+use tokio::{
+    io::{duplex, AsyncBufReadExt, AsyncWriteExt, BufReader},
+    task::JoinHandle,
+};
+
+use super::transport::Transport;
+
+pub const DOVECOT_GREETING: &str = "* OK [CAPABILITY IMAP4rev1 SASL-IR LOGIN-REFERRALS ID ENABLE IDLE LITERAL+ AUTH=PLAIN] Dovecot (Debian) ready.\r\n";
+// Gmail doesn't announce capabilities in its greeting
+pub const GMAIL_GREETING: &str = "* OK Gimap ready for requests from 192.0.2.1 a1b2c3d4\r\n";
+pub const GMAIL_CAPABILITY: &str = "* CAPABILITY IMAP4rev1 UNSELECT IDLE NAMESPACE QUOTA ID XLIST CHILDREN X-GM-EXT-1 UIDPLUS COMPRESS=DEFLATE ENABLE MOVE CONDSTORE ESEARCH UTF8=ACCEPT LIST-EXTENDED LIST-STATUS LITERAL- SPECIAL-USE APPENDLIMIT=35651584\r\n";
+
+// each step waits for the expected command line, if any, and then replays the responses
+// responses are usually text, octets allow for literals that aren't
+pub struct Step<R = &'static str> {
+    pub command: Option<&'static str>,
+    pub responses: R,
+}
+
+// serves the script over an in-memory stream, the handle fails if the client deviated from it
+pub fn serve<R: AsRef<[u8]> + Send + 'static>(script: Vec<Step<R>>) -> (Transport, JoinHandle<()>) {
+    let (client, server) = duplex(64 * 1024);
+    let handle = tokio::spawn(async move {
+        let mut server = BufReader::new(server);
+        for step in script {
+            if let Some(expected) = step.command {
+                let mut command = String::new();
+                (server.read_line(&mut command).await).expect("command should be readable");
+                assert_eq!(command, format!("{expected}\r\n"));
+            }
+            (server.write_all(step.responses.as_ref()).await)
+                .expect("responses should be writable");
+        }
+    });
+
+    (Transport::from_stream(client), handle)
+}
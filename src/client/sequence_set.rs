@@ -0,0 +1,138 @@
+use std::fmt::{self, Display, Formatter};
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum SequenceEnd {
+    Number(u32),
+    // the largest number in use, including messages arriving later
+    Star,
+}
+
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub struct SequenceRange {
+    start: u32,
+    end: SequenceEnd,
+}
+
+impl Display for SequenceRange {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self.end {
+            SequenceEnd::Number(end) if end == self.start => write!(f, "{}", self.start),
+            SequenceEnd::Number(end) => write!(f, "{}:{end}", self.start),
+            SequenceEnd::Star => write!(f, "{}:*", self.start),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub struct SequenceSet {
+    ranges: Vec<SequenceRange>,
+}
+
+impl SequenceSet {
+    pub fn all() -> Self {
+        Self {
+            ranges: vec![SequenceRange {
+                start: 1,
+                end: SequenceEnd::Star,
+            }],
+        }
+    }
+
+    // all uids that existed when the mailbox was selected, unlike 1:* not including later mail
+    pub fn below(uid_next: u32) -> Self {
+        if uid_next > 1 {
+            Self {
+                ranges: vec![SequenceRange {
+                    start: 1,
+                    end: SequenceEnd::Number(uid_next - 1),
+                }],
+            }
+        } else {
+            Self { ranges: Vec::new() }
+        }
+    }
+
+    // coalesces consecutive numbers into ranges
+    pub fn from_numbers(numbers: impl IntoIterator<Item = u32>) -> Self {
+        let mut numbers: Vec<u32> = numbers.into_iter().collect();
+        numbers.sort_unstable();
+        numbers.dedup();
+        let mut ranges: Vec<SequenceRange> = Vec::new();
+        for number in numbers {
+            match ranges.last_mut() {
+                Some(SequenceRange {
+                    end: SequenceEnd::Number(end),
+                    ..
+                }) if end.checked_add(1) == Some(number) => *end = number,
+                _ => ranges.push(SequenceRange {
+                    start: number,
+                    end: SequenceEnd::Number(number),
+                }),
+            }
+        }
+
+        Self { ranges }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ranges.is_empty()
+    }
+
+    // a set ending in * can't be enumerated without knowing the mailbox
+    pub fn iter(&self) -> Option<impl Iterator<Item = u32> + '_> {
+        if self
+            .ranges
+            .iter()
+            .any(|range| range.end == SequenceEnd::Star)
+        {
+            return None;
+        }
+        Some(self.ranges.iter().flat_map(|range| match range.end {
+            SequenceEnd::Number(end) => range.start..=end,
+            SequenceEnd::Star => unreachable!("star ended ranges are rejected above"),
+        }))
+    }
+}
+
+impl Display for SequenceSet {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let mut ranges = self.ranges.iter();
+        if let Some(first) = ranges.next() {
+            write!(f, "{first}")?;
+        }
+        for range in ranges {
+            write!(f, ",{range}")?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_all_with_star() {
+        assert_eq!(SequenceSet::all().to_string(), "1:*");
+        assert!(SequenceSet::all().iter().is_none());
+    }
+
+    #[test]
+    fn render_uids_below_uid_next() {
+        assert_eq!(SequenceSet::below(4).to_string(), "1:3");
+        assert_eq!(SequenceSet::below(2).to_string(), "1");
+        assert!(SequenceSet::below(1).is_empty());
+    }
+
+    #[test]
+    fn coalesce_numbers_into_ranges() {
+        let set = SequenceSet::from_numbers([7, 3, 1, 2, 9, 8, 3]);
+        assert_eq!(set.to_string(), "1:3,7:9");
+        assert_eq!(
+            set.iter()
+                .expect("set should be enumerable")
+                .collect::<Vec<_>>(),
+            vec![1, 2, 3, 7, 8, 9]
+        );
+    }
+}
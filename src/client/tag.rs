@@ -0,0 +1,32 @@
+// tags are a fixed 5 characters, a0001 to a9999, wrapping around after that
+// only one command is in flight at a time, so a wrapped tag can't be confused with an earlier one
+// a reconnect starts over with a new generator, responses of the old connection can't arrive on it
+pub struct TagGenerator {
+    next: u16,
+}
+
+impl TagGenerator {
+    pub fn new() -> Self {
+        Self { next: 1 }
+    }
+
+    pub fn next(&mut self) -> String {
+        let tag = format!("a{:04}", self.next);
+        self.next = if self.next == 9999 { 1 } else { self.next + 1 };
+        tag
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wrap_around_at_fixed_width() {
+        let mut tags = TagGenerator { next: 9998 };
+        assert_eq!(tags.next(), "a9998");
+        assert_eq!(tags.next(), "a9999");
+        assert_eq!(tags.next(), "a0001");
+        assert_eq!(TagGenerator::new().next(), "a0001");
+    }
+}
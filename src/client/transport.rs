@@ -0,0 +1,70 @@
+use std::process::Stdio;
+
+use tokio::{
+    io::{split, AsyncRead, AsyncWrite, BufReader, BufWriter},
+    process::{Child, Command},
+};
+use tokio_native_tls::{native_tls, TlsConnector};
+
+use super::{connect, proxy};
+use crate::config::Config;
+
+pub type Reader = BufReader<Box<dyn AsyncRead + Unpin + Send>>;
+pub type Writer = BufWriter<Box<dyn AsyncWrite + Unpin + Send>>;
+
+// the byte stream IMAP is spoken over, independent of how it was established
+pub struct Transport {
+    pub reader: Reader,
+    pub writer: Writer,
+    // process behind a tunnel, killed when dropped
+    pub tunnel: Option<Child>,
+}
+
+impl Transport {
+    // implicit tls, directly or via proxy, unless a tunnel is configured
+    pub async fn open(config: &Config) -> Self {
+        match config.tunnel() {
+            Some(tunnel) => Self::tunnel(tunnel),
+            None => Self::tls(config).await,
+        }
+    }
+
+    async fn tls(config: &Config) -> Self {
+        let tls = native_tls::TlsConnector::new().expect("native tls should be available");
+        let tls = TlsConnector::from(tls);
+        let stream = match config.proxy() {
+            Some(proxy) => proxy::connect(proxy, config.host(), config.port).await,
+            None => connect::connect(config.host(), config.port, config.bind_address()).await,
+        };
+        let stream =
+            (tls.connect(config.host(), stream).await).expect("upgrading to tls should succeed");
+        Self::from_stream(stream)
+    }
+
+    fn tunnel(command: &str) -> Self {
+        let mut child = Command::new("sh")
+            .arg("-c")
+            .arg(command)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .expect("tunnel command should be executable");
+        let stdout = child.stdout.take().expect("stdout should be piped");
+        let stdin = child.stdin.take().expect("stdin should be piped");
+        Self {
+            reader: BufReader::new(Box::new(stdout)),
+            writer: BufWriter::new(Box::new(stdin)),
+            tunnel: Some(child),
+        }
+    }
+
+    pub fn from_stream(stream: impl AsyncRead + AsyncWrite + Unpin + Send + 'static) -> Self {
+        let (reader, writer) = split(stream);
+        Self {
+            reader: BufReader::new(Box::new(reader)),
+            writer: BufWriter::new(Box::new(writer)),
+            tunnel: None,
+        }
+    }
+}
@@ -0,0 +1,106 @@
+// modified UTF-7 for mailbox names as defined by
+// https://datatracker.ietf.org/doc/html/rfc3501#section-5.1.3
+use base64::{
+    alphabet::IMAP_MUTF7,
+    engine::{general_purpose::NO_PAD, GeneralPurpose},
+    Engine,
+};
+
+const MODIFIED_BASE64: GeneralPurpose = GeneralPurpose::new(&IMAP_MUTF7, NO_PAD);
+
+fn is_printable(input: char) -> bool {
+    (' '..='~').contains(&input)
+}
+
+pub fn encode(name: &str) -> String {
+    let mut encoded = String::with_capacity(name.len());
+    let mut shifted = Vec::new();
+    for c in name.chars() {
+        if is_printable(c) {
+            flush_shifted(&mut encoded, &mut shifted);
+            if c == '&' {
+                encoded.push_str("&-");
+            } else {
+                encoded.push(c);
+            }
+        } else {
+            let mut buffer = [0; 2];
+            for unit in c.encode_utf16(&mut buffer) {
+                shifted.extend_from_slice(&unit.to_be_bytes());
+            }
+        }
+    }
+    flush_shifted(&mut encoded, &mut shifted);
+
+    encoded
+}
+
+fn flush_shifted(encoded: &mut String, shifted: &mut Vec<u8>) {
+    if !shifted.is_empty() {
+        encoded.push('&');
+        encoded.push_str(&MODIFIED_BASE64.encode(&shifted));
+        encoded.push('-');
+        shifted.clear();
+    }
+}
+
+#[allow(dead_code)]
+pub fn decode(name: &str) -> Result<String, ()> {
+    let mut decoded = String::with_capacity(name.len());
+    let mut rest = name;
+    while let Some(start) = rest.find('&') {
+        decoded.push_str(&rest[..start]);
+        let (shifted, after) = rest[start + 1..].split_once('-').ok_or(())?;
+        if shifted.is_empty() {
+            decoded.push('&');
+        } else {
+            let bytes = MODIFIED_BASE64.decode(shifted).map_err(|_| ())?;
+            if bytes.len() % 2 != 0 {
+                return Err(());
+            }
+            let units: Vec<u16> = bytes
+                .chunks_exact(2)
+                .map(|unit| u16::from_be_bytes([unit[0], unit[1]]))
+                .collect();
+            decoded.push_str(&String::from_utf16(&units).map_err(|_| ())?);
+        }
+        rest = after;
+    }
+    decoded.push_str(rest);
+
+    Ok(decoded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode_unicode_mailbox_names() {
+        assert_eq!(encode("Entwürfe"), "Entw&APw-rfe");
+        assert_eq!(
+            encode("~peter/mail/台北/日本語"),
+            "~peter/mail/&U,BTFw-/&ZeVnLIqe-"
+        );
+        assert_eq!(encode("Tom & Jerry"), "Tom &- Jerry");
+        assert_eq!(encode("INBOX"), "INBOX");
+    }
+
+    #[test]
+    fn round_trip_unicode_mailbox_names() {
+        for name in [
+            "Entwürfe",
+            "Gelöschte Elemente",
+            "Входящие",
+            "📬 Inbox",
+            "a&b",
+        ] {
+            assert_eq!(decode(&encode(name)), Ok(name.to_string()));
+        }
+    }
+
+    #[test]
+    fn reject_unterminated_shift() {
+        assert_eq!(decode("Entw&APw"), Err(()));
+    }
+}
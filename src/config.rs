@@ -1,57 +1,328 @@
 use ::std::env;
 use std::{
-    fs::{create_dir, read_to_string},
-    path::PathBuf,
+    collections::HashMap,
+    fs::{create_dir, read_to_string, set_permissions, DirBuilder, Permissions},
+    net::IpAddr,
+    os::unix::fs::{DirBuilderExt, PermissionsExt},
+    path::{Path, PathBuf},
     process::Command,
     str::FromStr,
+    time::Duration,
 };
 
-use serde::Deserialize;
+use serde::{Deserialize, Deserializer};
+
+use crate::maildir::{hostname, is_unique_file_prefix, DEFAULT_FILE_PREFIX};
 
 #[derive(Deserialize)]
 pub struct Config {
     user: String,
-    password_cmd: String,
+    #[serde(flatten)]
+    password: PasswordSource,
     host: String,
     pub port: u16,
+    #[serde(default = "default_maildir")]
+    maildir: PathBuf,
+    #[serde(default = "default_state_dir")]
+    state_dir: PathBuf,
+    #[serde(default)]
+    layout: Layout,
+    #[serde(default)]
+    mailboxes: HashMap<String, MailboxConfig>,
+    // IMAP keywords mapped to the maildir keyword letters a-z, as MUAs disagree on which letter
+    // stands for which keyword
+    #[serde(default)]
+    keywords: HashMap<String, char>,
+    proxy: Option<Proxy>,
+    // source address of the connection on multi-homed hosts, unused when connecting via proxy
+    bind_address: Option<IpAddr>,
+    // shell command speaking IMAP on stdin and stdout, e.g. over ssh, used instead of connecting
+    // to host and port, which usually greets with PREAUTH
+    tunnel: Option<String>,
+    // template of the unique part of new maildir file names, see maildir::DEFAULT_FILE_PREFIX
+    #[serde(default = "default_file_prefix")]
+    file_prefix: String,
+    // replaces the hostname in new maildir file names, e.g. to keep them stable across machines
+    maildir_host: Option<String>,
+    // separates the unique part of maildir file names from the flags, ';' where ':' is forbidden
+    #[serde(default = "default_info_separator")]
+    info_separator: char,
+    // modes of the maildir's directories and mail files, e.g. 0o750 and 0o640 to share them with a
+    // group, applied regardless of the umask
+    #[serde(default = "default_dir_mode")]
+    dir_mode: u32,
+    #[serde(default = "default_file_mode")]
+    file_mode: u32,
+    #[serde(default)]
+    timeouts: Timeouts,
+}
+
+// how long to wait for the next response of the server before giving up on a command, in seconds
+// in the config
+// waiting restarts with every response, so only a stalled server hits them, not a slow one
+#[derive(Deserialize, Clone, Debug, PartialEq)]
+#[serde(default)]
+pub struct Timeouts {
+    // also covers asking for capabilities
+    #[serde(deserialize_with = "seconds")]
+    pub login: Duration,
+    #[serde(deserialize_with = "seconds")]
+    pub select: Duration,
+    #[serde(deserialize_with = "seconds")]
+    pub fetch: Duration,
+    #[serde(deserialize_with = "seconds")]
+    pub append: Duration,
+    #[serde(deserialize_with = "seconds")]
+    pub store: Duration,
+}
+
+impl Default for Timeouts {
+    fn default() -> Self {
+        Self {
+            login: Duration::from_secs(30),
+            select: Duration::from_secs(30),
+            fetch: Duration::from_secs(60),
+            append: Duration::from_secs(60),
+            store: Duration::from_secs(30),
+        }
+    }
+}
+
+fn seconds<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+    u64::deserialize(deserializer).map(Duration::from_secs)
+}
+
+#[derive(Deserialize, Default)]
+struct MailboxConfig {
+    #[serde(default)]
+    direction: Direction,
+    // overrides the global keyword letters for this mailbox
+    #[serde(default)]
+    keywords: HashMap<String, char>,
+    // flags whose changes are neither pushed nor pulled, e.g. \Seen to keep unread state
+    // independent between clients
+    #[serde(default)]
+    ignore_flags: Vec<String>,
+}
+
+// which way changes are propagated for a mailbox
+// a one-way mailbox never has changes of the other side applied, regardless of conflicts
+#[derive(Deserialize, Default, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    #[default]
+    Both,
+    // mirror the server, never push local flag changes or deletions
+    Pull,
+    // only push local changes, never apply remote changes other than deletions originating locally
+    Push,
+}
+
+// tls is still negotiated with the mail host through the tunnel
+#[derive(Deserialize)]
+pub struct Proxy {
+    pub scheme: ProxyScheme,
+    pub host: String,
+    pub port: u16,
+    pub user: Option<String>,
+    pub password: Option<String>,
+}
+
+impl Proxy {
+    // one without the other would silently connect without authenticating
+    fn check_credentials(&self) -> Result<(), String> {
+        match (&self.user, &self.password) {
+            (Some(_), None) => Err("user is set without password".to_string()),
+            (None, Some(_)) => Err("password is set without user".to_string()),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum ProxyScheme {
+    Socks5,
+    // HTTP CONNECT
+    Http,
+}
+
+#[derive(Deserialize)]
+enum PasswordSource {
+    #[serde(rename = "password_cmd")]
+    Command(String),
+    // name of an environment variable holding the password, e.g. from a container secret
+    #[serde(rename = "password_env")]
+    Env(String),
+}
+
+// how the server's mailbox hierarchy is laid out below the maildir
+#[derive(Deserialize, Default, Clone, Copy, Debug, PartialEq)]
+pub enum Layout {
+    // Work/Projects/Foo becomes the directory Work/Projects/Foo
+    #[default]
+    #[serde(rename = "nested")]
+    Nested,
+    // Work/Projects/Foo becomes the folder .Work.Projects.Foo, INBOX the maildir itself
+    #[serde(rename = "maildir++")]
+    MaildirPlusPlus,
+}
+
+impl Layout {
+    // mailbox names come from the server, so anything that would be path syntax is escaped to keep
+    // every mailbox inside the maildir and apart from the others
+    pub fn mailbox_path(self, mailbox: &str, delimiter: char) -> PathBuf {
+        let components = mailbox.split(delimiter);
+        match self {
+            Layout::Nested => components
+                .map(|component| escape_path_component(component, self))
+                .collect(),
+            Layout::MaildirPlusPlus => {
+                if mailbox.eq_ignore_ascii_case("INBOX") {
+                    PathBuf::new()
+                } else {
+                    let mut folder = String::new();
+                    for component in components {
+                        folder.push('.');
+                        folder.push_str(&escape_path_component(component, self));
+                    }
+                    PathBuf::from(folder)
+                }
+            }
+        }
+    }
+}
+
+// percent-encodes '%' itself, so that escaped and unescaped names never collide
+// under Maildir++ '.' separates the levels, so a literal one has to be escaped, while nested
+// directories named like the maildir's own subdirectories would end up in them
+fn escape_path_component(component: &str, layout: Layout) -> String {
+    let escape_dots = layout == Layout::MaildirPlusPlus;
+    match component {
+        "" => "%".to_string(),
+        "." | ".." => component.replace('.', "%2E"),
+        "cur" if !escape_dots => "%63ur".to_string(),
+        "new" if !escape_dots => "%6Eew".to_string(),
+        "tmp" if !escape_dots => "%74mp".to_string(),
+        _ => {
+            let mut escaped = String::with_capacity(component.len());
+            for c in component.chars() {
+                match c {
+                    '%' => escaped.push_str("%25"),
+                    '/' => escaped.push_str("%2F"),
+                    '.' if escape_dots => escaped.push_str("%2E"),
+                    _ => escaped.push(c),
+                }
+            }
+            escaped
+        }
+    }
 }
 
 impl Config {
-    pub fn load_from_file() -> Self {
-        let mut config_dir = if let Ok(config_home) = env::var("XDG_CONFIG_HOME") {
-            PathBuf::from_str(&config_home).expect("XDG_CONFIG_HOME should be a parseable path")
-        } else {
-            let mut config_home = PathBuf::from_str(&env::var("HOME").expect("HOME should be set"))
-                .expect("XDG_CONFIG_HOME should be a parseable path");
-            config_home.push(".config");
-            config_home
+    // path overrides the XDG config dir, either as directory containing config.toml or as file
+    // only parses the config, check and create_dirs are left to the caller, so that checking
+    // the config can report every problem without touching the file system
+    pub fn load_from_file(path: Option<&Path>) -> Result<Self, String> {
+        let config_file = match path {
+            Some(path) if path.is_dir() => path.join("config.toml"),
+            Some(path) => path.to_path_buf(),
+            None => default_config_file(),
         };
-        config_dir.push(env!("CARGO_PKG_NAME"));
-        if !config_dir.exists() {
-            create_dir(&config_dir).expect("config_dir should be creatable");
+
+        let config_contents = read_to_string(&config_file)
+            .map_err(|error| format!("{}: {error}", config_file.display()))?;
+        toml::from_str(&config_contents)
+            .map_err(|error| format!("{}: {error}", config_file.display()))
+    }
+
+    // problems serde can't catch, each as what was checked and the problem with it
+    fn validate(&self) -> Vec<(String, Result<(), String>)> {
+        let keywords_valid = std::iter::once(&self.keywords)
+            .chain(self.mailboxes.values().map(|m| &m.keywords))
+            .all(|keywords| keywords.values().all(char::is_ascii_lowercase));
+        let mut checks = vec![
+            (
+                "keywords".to_string(),
+                require(keywords_valid, "letters should be between a and z"),
+            ),
+            (
+                format!("file_prefix {}", self.file_prefix),
+                require(
+                    is_unique_file_prefix(&self.file_prefix),
+                    "should contain {secs}, {pid}, {host} and {usecs} or {nanos}",
+                ),
+            ),
+            (
+                format!("maildir_host {}", self.maildir_host()),
+                require(
+                    self.maildir_host
+                        .as_ref()
+                        .is_none_or(|host| !host.is_empty()),
+                    "should not be empty",
+                ),
+            ),
+            (
+                format!("info_separator {}", self.info_separator),
+                require(
+                    [':', ';'].contains(&self.info_separator),
+                    "should be ':' or ';'",
+                ),
+            ),
+        ];
+        if let Some(proxy) = &self.proxy {
+            checks.push((
+                format!("proxy {}:{}", proxy.host, proxy.port),
+                proxy.check_credentials(),
+            ));
         }
-        config_dir.push("config.toml");
+        checks
+    }
 
-        let config_contents = read_to_string(config_dir).expect("config file should be readable");
-        toml::from_str(&config_contents).expect("config should be parseable")
+    pub fn create_dirs(&self) {
+        create_dir_with_mode(&self.maildir, self.dir_mode);
+        create_dir_with_mode(&self.state_dir, default_dir_mode());
     }
 
-    pub fn password(&self) -> String {
-        let mut cmd_parts = self.password_cmd.split(' ');
-        let mut cmd = Command::new(
-            cmd_parts
-                .next()
-                .expect("password_cmd should specify a program"),
-        );
-        for part in cmd_parts {
-            cmd.arg(part);
+    pub fn password(&self) -> Result<String, String> {
+        self.password_with(|variable| env::var(variable).ok())
+    }
+
+    // the environment is passed in, so that tests don't have to modify the process' one
+    fn password_with(&self, env: impl Fn(&str) -> Option<String>) -> Result<String, String> {
+        match &self.password {
+            PasswordSource::Command(password_cmd) => Ok(run_password_cmd(password_cmd)),
+            PasswordSource::Env(variable) => read_password_env(variable, env)
+                .map_err(|problem| format!("password_env {variable}: {problem}")),
         }
-        let output = cmd.output().expect("password_cmd should be executable");
+    }
 
-        String::from_utf8(output.stdout)
-            .expect("password_cmd should evaluate to password")
-            .trim_end()
-            .to_string()
+    // validates what can be checked without connecting to the server, without running the
+    // password command, which might prompt
+    pub fn check(&self) -> Vec<(String, Result<(), String>)> {
+        let password = match &self.password {
+            PasswordSource::Command(password_cmd) => (
+                format!("password_cmd {password_cmd}"),
+                find_program(password_cmd.split(' ').next().unwrap_or_default()),
+            ),
+            PasswordSource::Env(variable) => (
+                format!("password_env {variable}"),
+                read_password_env(variable, |variable| env::var(variable).ok()).map(|_| ()),
+            ),
+        };
+        let mut checks = vec![
+            password,
+            (
+                format!("maildir {}", self.maildir.display()),
+                check_writable(&self.maildir),
+            ),
+            (
+                format!("state_dir {}", self.state_dir.display()),
+                check_writable(&self.state_dir),
+            ),
+        ];
+        checks.extend(self.validate());
+        checks
     }
 
     pub fn host(&self) -> &str {
@@ -61,4 +332,462 @@ impl Config {
     pub fn user(&self) -> &str {
         self.user.as_str()
     }
+
+    pub fn maildir(&self) -> &Path {
+        self.maildir.as_path()
+    }
+
+    pub fn state_dir(&self) -> &Path {
+        self.state_dir.as_path()
+    }
+
+    pub fn proxy(&self) -> Option<&Proxy> {
+        self.proxy.as_ref()
+    }
+
+    pub fn file_prefix(&self) -> &str {
+        self.file_prefix.as_str()
+    }
+
+    pub fn maildir_host(&self) -> &str {
+        self.maildir_host.as_deref().unwrap_or_else(|| hostname())
+    }
+
+    pub fn dir_mode(&self) -> u32 {
+        self.dir_mode
+    }
+
+    pub fn file_mode(&self) -> u32 {
+        self.file_mode
+    }
+
+    pub fn info_separator(&self) -> char {
+        self.info_separator
+    }
+
+    pub fn tunnel(&self) -> Option<&str> {
+        self.tunnel.as_deref()
+    }
+
+    pub fn bind_address(&self) -> Option<IpAddr> {
+        self.bind_address
+    }
+
+    pub fn direction(&self, mailbox: &str) -> Direction {
+        self.mailboxes
+            .get(mailbox)
+            .map(|mailbox| mailbox.direction)
+            .unwrap_or_default()
+    }
+
+    // keywords are case-insensitive, so the configured spelling doesn't have to match the server's
+    pub fn keyword_letter(&self, mailbox: &str, keyword: &str) -> Option<char> {
+        let find = |keywords: &HashMap<String, char>| {
+            keywords
+                .iter()
+                .find(|(configured, _)| configured.eq_ignore_ascii_case(keyword))
+                .map(|(_, letter)| *letter)
+        };
+        self.mailboxes
+            .get(mailbox)
+            .and_then(|mailbox| find(&mailbox.keywords))
+            .or_else(|| find(&self.keywords))
+    }
+
+    // mirrored mailboxes are opened with EXAMINE, so local changes are never pushed
+    pub fn read_only(&self, mailbox: &str) -> bool {
+        self.direction(mailbox) == Direction::Pull
+    }
+
+    // flags are case-insensitive
+    pub fn ignores_flag(&self, mailbox: &str, flag: &str) -> bool {
+        self.mailboxes.get(mailbox).is_some_and(|mailbox| {
+            mailbox
+                .ignore_flags
+                .iter()
+                .any(|ignored| ignored.eq_ignore_ascii_case(flag))
+        })
+    }
+
+    pub fn timeouts(&self) -> &Timeouts {
+        &self.timeouts
+    }
+
+    pub fn mailbox_dir(&self, mailbox: &str, delimiter: char) -> PathBuf {
+        self.maildir
+            .join(self.layout.mailbox_path(mailbox, delimiter))
+    }
+}
+
+fn xdg_dir(variable: &str, fallback: &str) -> PathBuf {
+    if let Ok(xdg_home) = env::var(variable) {
+        PathBuf::from_str(&xdg_home).expect("XDG base directory should be a parseable path")
+    } else {
+        let mut xdg_home = PathBuf::from_str(&env::var("HOME").expect("HOME should be set"))
+            .expect("HOME should be a parseable path");
+        xdg_home.push(fallback);
+        xdg_home
+    }
+}
+
+fn default_config_file() -> PathBuf {
+    let mut config_dir = xdg_dir("XDG_CONFIG_HOME", ".config");
+    config_dir.push(env!("CARGO_PKG_NAME"));
+    if !config_dir.exists() {
+        create_dir(&config_dir).expect("config_dir should be creatable");
+    }
+    config_dir.join("config.toml")
+}
+
+fn find_program(program: &str) -> Result<(), String> {
+    let found = if program.contains('/') {
+        Path::new(program).is_file()
+    } else {
+        env::var_os("PATH")
+            .is_some_and(|path| env::split_paths(&path).any(|dir| dir.join(program).is_file()))
+    };
+    if found {
+        Ok(())
+    } else {
+        Err(format!("program {program} not found"))
+    }
+}
+
+fn require(valid: bool, problem: &str) -> Result<(), String> {
+    if valid {
+        Ok(())
+    } else {
+        Err(problem.to_string())
+    }
+}
+
+// directories are only created once syncing, so a missing one is fine as long as it can be
+fn check_writable(dir: &Path) -> Result<(), String> {
+    let dir = dir
+        .ancestors()
+        .find(|dir| dir.exists())
+        .expect("root directory should exist");
+    let probe = dir.join(format!(".{}-check", env!("CARGO_PKG_NAME")));
+    std::fs::write(&probe, "").map_err(|error| error.to_string())?;
+    std::fs::remove_file(&probe).map_err(|error| error.to_string())
+}
+
+// the value is never part of the error, so it can't end up in any output
+fn read_password_env(
+    variable: &str,
+    env: impl Fn(&str) -> Option<String>,
+) -> Result<String, String> {
+    match env(variable) {
+        Some(password) if !password.is_empty() => Ok(password),
+        Some(_) => Err("is empty".to_string()),
+        None => Err("is not set".to_string()),
+    }
+}
+
+fn run_password_cmd(password_cmd: &str) -> String {
+    let mut cmd_parts = password_cmd.split(' ');
+    let mut cmd = Command::new(
+        cmd_parts
+            .next()
+            .expect("password_cmd should specify a program"),
+    );
+    for part in cmd_parts {
+        cmd.arg(part);
+    }
+    let output = cmd.output().expect("password_cmd should be executable");
+
+    String::from_utf8(output.stdout)
+        .expect("password_cmd should evaluate to password")
+        .trim_end()
+        .to_string()
+}
+
+fn default_maildir() -> PathBuf {
+    let mut maildir = xdg_dir("XDG_DATA_HOME", ".local/share");
+    maildir.push("mail");
+    maildir
+}
+
+fn default_state_dir() -> PathBuf {
+    let mut state_dir = xdg_dir("XDG_STATE_HOME", ".local/state");
+    state_dir.push(env!("CARGO_PKG_NAME"));
+    state_dir
+}
+
+fn default_file_prefix() -> String {
+    DEFAULT_FILE_PREFIX.to_string()
+}
+
+fn default_info_separator() -> char {
+    ':'
+}
+
+fn default_dir_mode() -> u32 {
+    0o700
+}
+
+fn default_file_mode() -> u32 {
+    0o600
+}
+
+fn create_dir_with_mode(dir: &Path, mode: u32) {
+    if !dir.exists() {
+        DirBuilder::new()
+            .recursive(true)
+            .mode(mode)
+            .create(dir)
+            .expect("directory should be creatable");
+        // the mode passed on creation is restricted by the umask
+        set_permissions(dir, Permissions::from_mode(mode))
+            .expect("permissions of directory should be settable");
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_password_from_environment_variable() {
+        let config: Config = toml::from_str(
+            "user = \"me\"\npassword_env = \"IMAPMAILDIR_TEST_PASSWORD\"\nhost = \"example.org\"\nport = 993\n",
+        )
+        .expect("config should be parseable");
+        let env = |variable: &str| {
+            (variable == "IMAPMAILDIR_TEST_PASSWORD").then(|| "secret".to_string())
+        };
+        assert_eq!(config.password_with(env), Ok("secret".to_string()));
+        assert_eq!(
+            config.password_with(|_| Some(String::new())),
+            Err("password_env IMAPMAILDIR_TEST_PASSWORD: is empty".to_string())
+        );
+        assert_eq!(
+            config.password_with(|_| None),
+            Err("password_env IMAPMAILDIR_TEST_PASSWORD: is not set".to_string())
+        );
+    }
+
+    #[test]
+    fn load_config_from_given_path() {
+        let dir = env::temp_dir().join(format!("imapmaildir-config-{}", std::process::id()));
+        create_dir_with_mode(&dir, 0o700);
+        std::fs::write(
+            dir.join("config.toml"),
+            format!(
+                "user = \"me\"\npassword_cmd = \"pass mail\"\nhost = \"example.org\"\nport = 993\nmaildir = \"{0}/mail\"\nstate_dir = \"{0}/state\"\n",
+                dir.display()
+            ),
+        )
+        .expect("config should be writable");
+
+        let from_dir = Config::load_from_file(Some(&dir)).expect("config should be loadable");
+        let from_file = Config::load_from_file(Some(&dir.join("config.toml")))
+            .expect("config should be loadable");
+        let missing = Config::load_from_file(Some(&dir.join("missing.toml")));
+        std::fs::remove_dir_all(&dir).expect("config dir should be removable");
+        assert_eq!(from_dir.user(), "me");
+        assert_eq!(from_file.maildir(), dir.join("mail"));
+        assert!(!from_file.maildir().exists());
+        assert!(missing.is_err());
+    }
+
+    #[test]
+    fn report_missing_password_source() {
+        let config: Config = toml::from_str(
+            "user = \"me\"\npassword_env = \"IMAPMAILDIR_TEST_UNSET\"\nhost = \"example.org\"\nport = 993\nmaildir = \"/dev/null/mail\"\n",
+        )
+        .expect("config should be parseable");
+        let results = config.check();
+        assert_eq!(
+            results[0],
+            (
+                "password_env IMAPMAILDIR_TEST_UNSET".to_string(),
+                Err("is not set".to_string())
+            )
+        );
+        assert!(results[1].1.is_err());
+    }
+
+    #[test]
+    fn report_invalid_settings() {
+        let config: Config = toml::from_str(
+            "user = \"me\"\npassword_env = \"PASSWORD\"\nhost = \"example.org\"\nport = 993\nfile_prefix = \"{secs}\"\nmaildir_host = \"\"\ninfo_separator = \"!\"\n[keywords]\n\"$Junk\" = \"J\"\n",
+        )
+        .expect("config should be parseable");
+        let problems: Vec<_> = config
+            .validate()
+            .into_iter()
+            .filter_map(|(checked, result)| result.err().map(|_| checked))
+            .collect();
+        assert_eq!(
+            problems,
+            [
+                "keywords",
+                "file_prefix {secs}",
+                "maildir_host ",
+                "info_separator !"
+            ]
+        );
+    }
+
+    #[test]
+    fn report_proxy_user_without_password() {
+        let config: Config = toml::from_str(
+            "user = \"me\"\npassword_env = \"PASSWORD\"\nhost = \"example.org\"\nport = 993\n[proxy]\nscheme = \"socks5\"\nhost = \"localhost\"\nport = 1080\nuser = \"me\"\n",
+        )
+        .expect("config should be parseable");
+        assert_eq!(
+            config.check().last(),
+            Some(&(
+                "proxy localhost:1080".to_string(),
+                Err("user is set without password".to_string())
+            ))
+        );
+    }
+
+    #[test]
+    fn override_maildir_host() {
+        let config: Config = toml::from_str(
+            "user = \"me\"\npassword_env = \"PASSWORD\"\nhost = \"example.org\"\nport = 993\n",
+        )
+        .expect("config should be parseable");
+        assert_eq!(config.maildir_host(), hostname());
+        let config: Config = toml::from_str(
+            "user = \"me\"\npassword_env = \"PASSWORD\"\nhost = \"example.org\"\nport = 993\nmaildir_host = \"laptop\"\n",
+        )
+        .expect("config should be parseable");
+        assert_eq!(config.maildir_host(), "laptop");
+    }
+
+    #[test]
+    fn override_some_timeouts() {
+        let config: Config = toml::from_str(
+            "user = \"me\"\npassword_env = \"PASSWORD\"\nhost = \"example.org\"\nport = 993\n[timeouts]\nfetch = 300\n",
+        )
+        .expect("config should be parseable");
+        assert_eq!(
+            config.timeouts(),
+            &Timeouts {
+                fetch: Duration::from_secs(300),
+                ..Timeouts::default()
+            }
+        );
+    }
+
+    #[test]
+    fn read_per_mailbox_direction() {
+        let config: Config = toml::from_str(
+            "user = \"me\"\npassword_cmd = \"pass mail\"\nhost = \"example.org\"\nport = 993\n[mailboxes.Archive]\ndirection = \"pull\"\n",
+        )
+        .expect("config should be parseable");
+        assert_eq!(config.direction("Archive"), Direction::Pull);
+        assert_eq!(config.direction("INBOX"), Direction::Both);
+        assert!(config.read_only("Archive"));
+        assert!(!config.ignores_flag("Archive", "\\Seen"));
+        assert!(!config.read_only("INBOX"));
+    }
+
+    #[test]
+    fn ignore_configured_flags() {
+        let config: Config = toml::from_str(
+            "user = \"me\"\npassword_cmd = \"pass mail\"\nhost = \"example.org\"\nport = 993\n[mailboxes.INBOX]\nignore_flags = [\"\\\\Seen\"]\n",
+        )
+        .expect("config should be parseable");
+        assert!(config.ignores_flag("INBOX", "\\seen"));
+        assert!(!config.ignores_flag("INBOX", "\\Flagged"));
+        assert!(!config.ignores_flag("Archive", "\\Seen"));
+    }
+
+    #[test]
+    fn map_keywords_to_letters() {
+        let config: Config = toml::from_str(
+            "user = \"me\"\npassword_cmd = \"pass mail\"\nhost = \"example.org\"\nport = 993\n[keywords]\n\"$Forwarded\" = \"a\"\n\"$Junk\" = \"b\"\n[mailboxes.Archive.keywords]\n\"$Junk\" = \"c\"\n",
+        )
+        .expect("config should be parseable");
+        assert_eq!(config.keyword_letter("INBOX", "$forwarded"), Some('a'));
+        assert_eq!(config.keyword_letter("INBOX", "$Junk"), Some('b'));
+        assert_eq!(config.keyword_letter("Archive", "$Junk"), Some('c'));
+        assert_eq!(config.keyword_letter("Archive", "$Forwarded"), Some('a'));
+        assert_eq!(config.keyword_letter("INBOX", "$NotJunk"), None);
+    }
+
+    #[test]
+    fn map_hierarchy_to_nested_directories() {
+        assert_eq!(
+            Layout::Nested.mailbox_path("Work/Projects/Foo", '/'),
+            PathBuf::from("Work/Projects/Foo")
+        );
+        assert_eq!(
+            Layout::Nested.mailbox_path("INBOX", '/'),
+            PathBuf::from("INBOX")
+        );
+    }
+
+    #[test]
+    fn map_hierarchy_to_maildir_plus_plus_folders() {
+        assert_eq!(
+            Layout::MaildirPlusPlus.mailbox_path("Work/Projects/Foo", '/'),
+            PathBuf::from(".Work.Projects.Foo")
+        );
+        assert_eq!(
+            Layout::MaildirPlusPlus.mailbox_path("INBOX", '/'),
+            PathBuf::new()
+        );
+    }
+
+    #[test]
+    fn keep_mailboxes_inside_maildir() {
+        assert_eq!(
+            Layout::Nested.mailbox_path("../../etc", '/'),
+            PathBuf::from("%2E%2E/%2E%2E/etc")
+        );
+        assert_eq!(
+            Layout::Nested.mailbox_path("/etc/./passwd", '/'),
+            PathBuf::from("%/etc/%2E/passwd")
+        );
+        assert_eq!(
+            Layout::Nested.mailbox_path("a/../b", '.'),
+            PathBuf::from("a%2F/%/%2Fb")
+        );
+        assert_eq!(
+            Layout::Nested.mailbox_path("100%", '/'),
+            PathBuf::from("100%25")
+        );
+        assert_eq!(
+            Layout::Nested.mailbox_path("INBOX/new", '/'),
+            PathBuf::from("INBOX/%6Eew")
+        );
+        assert_eq!(
+            Layout::Nested.mailbox_path("cur/tmp", '/'),
+            PathBuf::from("%63ur/%74mp")
+        );
+        assert_eq!(
+            Layout::MaildirPlusPlus.mailbox_path("INBOX/new", '/'),
+            PathBuf::from(".INBOX.new")
+        );
+        assert_eq!(
+            Layout::MaildirPlusPlus.mailbox_path("", '/'),
+            PathBuf::from(".%")
+        );
+        assert_eq!(
+            Layout::MaildirPlusPlus.mailbox_path("../x", '/'),
+            PathBuf::from(".%2E%2E.x")
+        );
+    }
+
+    #[test]
+    fn keep_dotted_maildir_plus_plus_folders_apart() {
+        assert_eq!(
+            Layout::MaildirPlusPlus.mailbox_path("a.b", '/'),
+            PathBuf::from(".a%2Eb")
+        );
+        assert_eq!(
+            Layout::MaildirPlusPlus.mailbox_path("a/b", '/'),
+            PathBuf::from(".a.b")
+        );
+        assert_eq!(
+            Layout::MaildirPlusPlus.mailbox_path("a/b", '.'),
+            PathBuf::from(".a%2Fb")
+        );
+    }
 }
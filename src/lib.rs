@@ -0,0 +1,13 @@
+mod client;
+mod config;
+mod maildir;
+mod mbox;
+mod parts;
+
+pub use client::{
+    AppendError, Client, FetchError, FlagChange, LoginError, MessagePart, MessageStructure,
+    SelectError, SequenceSet, StoreError, Thread, ThreadAlgorithm, ThreadError, UnselectError,
+};
+pub use config::{Config, Direction, Layout, Timeouts};
+pub use mbox::{export as export_mbox, import as import_mbox};
+pub use parts::download as download_part;
@@ -0,0 +1,183 @@
+use std::{
+    fs::{self, File, OpenOptions, Permissions},
+    io::{self, Seek, SeekFrom, Write},
+    os::unix::fs::{FileExt, OpenOptionsExt, PermissionsExt},
+    path::{Path, PathBuf},
+    sync::OnceLock,
+    time::Duration,
+};
+
+use rustix::system::uname;
+
+// unique part of a maildir file name, https://cr.yp.to/proto/maildir.html
+// placeholders:
+// {secs}: seconds since the epoch
+// {usecs}: microseconds within that second
+// {nanos}: nanoseconds within that second
+// {pid}: process id
+// {host}: hostname
+pub const DEFAULT_FILE_PREFIX: &str = "{secs}.P{pid}N{nanos}.{host}";
+// as delivered by Dovecot
+#[allow(dead_code)]
+pub const DOVECOT_FILE_PREFIX: &str = "{secs}.M{usecs}P{pid}.{host}";
+
+// a name is only unique if it tells apart hosts, processes and deliveries within a second
+pub fn is_unique_file_prefix(template: &str) -> bool {
+    ["{secs}", "{pid}", "{host}"]
+        .iter()
+        .all(|placeholder| template.contains(placeholder))
+        && (template.contains("{usecs}") || template.contains("{nanos}"))
+}
+
+#[allow(dead_code)]
+pub fn file_prefix(template: &str, since_epoch: Duration, pid: u32, host: &str) -> String {
+    template
+        .replace("{secs}", &since_epoch.as_secs().to_string())
+        .replace("{usecs}", &since_epoch.subsec_micros().to_string())
+        .replace("{nanos}", &since_epoch.subsec_nanos().to_string())
+        .replace("{pid}", &pid.to_string())
+        .replace("{host}", &escape_host(host))
+}
+
+// looked up once instead of for every new mail, it doesn't change during a run
+pub fn hostname() -> &'static str {
+    static HOSTNAME: OnceLock<String> = OnceLock::new();
+    HOSTNAME.get_or_init(|| uname().nodename().to_string_lossy().into_owned())
+}
+
+// / and : can't be part of a file name, respectively would be confused with the info separator
+#[allow(dead_code)]
+fn escape_host(host: &str) -> String {
+    host.replace('/', "\\057").replace(':', "\\072")
+}
+
+// the mode passed on creation is restricted by the umask, so it is set again explicitly
+#[allow(dead_code)]
+pub fn create_mail_file(path: &Path, mode: u32) -> io::Result<File> {
+    let file = OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .mode(mode)
+        .open(path)?;
+    file.set_permissions(Permissions::from_mode(mode))?;
+    Ok(file)
+}
+
+// ':' as in the spec, or ';' on filesystems that forbid ':', like VFAT or SMB mounts
+#[allow(dead_code)]
+pub fn file_name(prefix: &str, separator: char, letters: &str) -> String {
+    format!("{prefix}{separator}2,{letters}")
+}
+
+// parts fetched on their own are kept next to, not in cur/ and new/, so that MUAs don't take them
+// for mail, e.g. parts/7.2.1 for part 2.1 of the mail with uid 7
+// they are named by uid, as nothing maps uids to the local mail files yet
+pub fn part_path(maildir: &Path, uid: u32, part: &[u32]) -> PathBuf {
+    let mut name = uid.to_string();
+    for number in part {
+        name.push_str(&format!(".{number}"));
+    }
+    maildir.join("parts").join(name)
+}
+
+// a range fetched from origin on is written at that offset, so that ranges fetched one after the
+// other add up to the whole part, while a whole part replaces whatever was written before
+pub fn write_part(path: &Path, origin: Option<u32>, content: &[u8], mode: u32) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    let mut file = OpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(origin.is_none())
+        .mode(mode)
+        .open(path)?;
+    file.set_permissions(Permissions::from_mode(mode))?;
+    match origin {
+        Some(origin) => file.write_all_at(content, origin.into()),
+        None => {
+            file.seek(SeekFrom::Start(0))?;
+            file.write_all(content)
+        }
+    }
+}
+
+// splits a file name into its unique prefix and flag letters
+// mail in new/ has no info yet, so no letters either
+#[allow(dead_code)]
+pub fn parse_file_name(name: &str, separator: char) -> (&str, &str) {
+    match name.rsplit_once(separator) {
+        Some((prefix, info)) => (prefix, info.strip_prefix("2,").unwrap_or_default()),
+        None => (name, ""),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn render_file_prefix_templates() {
+        let since_epoch = Duration::new(1_700_000_000, 123_456_789);
+        assert_eq!(
+            file_prefix(DEFAULT_FILE_PREFIX, since_epoch, 42, "mail.example.org"),
+            "1700000000.P42N123456789.mail.example.org"
+        );
+        assert_eq!(
+            file_prefix(DOVECOT_FILE_PREFIX, since_epoch, 42, "mail.example.org"),
+            "1700000000.M123456P42.mail.example.org"
+        );
+    }
+
+    #[test]
+    fn escape_hostname_in_file_names() {
+        let prefix = file_prefix(
+            DEFAULT_FILE_PREFIX,
+            Duration::new(1_700_000_000, 5),
+            42,
+            "weird/host:name",
+        );
+        assert_eq!(prefix, "1700000000.P42N5.weird\\057host\\072name");
+        for separator in [':', ';'] {
+            let name = file_name(&prefix, separator, "FS");
+            assert_eq!(parse_file_name(&name, separator), (prefix.as_str(), "FS"));
+        }
+        assert_eq!(file_name(&prefix, ';', "S"), format!("{prefix};2,S"));
+        assert_eq!(parse_file_name(&prefix, ':'), (prefix.as_str(), ""));
+    }
+
+    #[test]
+    fn create_mail_files_with_configured_mode() {
+        let path = std::env::temp_dir().join(format!("imapmaildir-mode-{}", std::process::id()));
+        let file = create_mail_file(&path, 0o640).expect("file should be creatable");
+        let mode = file
+            .metadata()
+            .expect("metadata should be readable")
+            .permissions()
+            .mode();
+        std::fs::remove_file(&path).expect("file should be removable");
+        assert_eq!(mode & 0o777, 0o640);
+    }
+
+    #[test]
+    fn assemble_part_from_ranges() {
+        let maildir = std::env::temp_dir().join(format!("imapmaildir-part-{}", std::process::id()));
+        let path = part_path(&maildir, 7, &[2, 1]);
+        assert_eq!(path, maildir.join("parts/7.2.1"));
+        write_part(&path, Some(4), b"5678", 0o600).expect("part should be writable");
+        write_part(&path, Some(0), b"1234", 0o600).expect("part should be writable");
+        let assembled = std::fs::read(&path).expect("part should be readable");
+        write_part(&path, None, b"whole", 0o600).expect("part should be writable");
+        let replaced = std::fs::read(&path).expect("part should be readable");
+        std::fs::remove_dir_all(&maildir).expect("maildir should be removable");
+        assert_eq!(assembled, b"12345678");
+        assert_eq!(replaced, b"whole");
+    }
+
+    #[test]
+    fn reject_templates_that_may_collide() {
+        assert!(is_unique_file_prefix(DEFAULT_FILE_PREFIX));
+        assert!(is_unique_file_prefix(DOVECOT_FILE_PREFIX));
+        assert!(!is_unique_file_prefix("{secs}.{host}"));
+    }
+}
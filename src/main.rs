@@ -1,11 +1,246 @@
-use client::Client;
-use config::Config;
+use std::{path::PathBuf, process::ExitCode};
 
-mod client;
-mod config;
+use clap::Parser;
+use imapmaildir::{
+    download_part, export_mbox, import_mbox, Client, Config, MessagePart, SequenceSet, Thread,
+    ThreadAlgorithm,
+};
+
+#[derive(Parser)]
+#[command(version, about)]
+struct Args {
+    // doc comments are clap's help texts
+    /// Config file, or directory containing config.toml, instead of $XDG_CONFIG_HOME/imapmaildir
+    #[arg(long)]
+    config: Option<PathBuf>,
+    /// Validate the config without connecting to the server
+    #[arg(long)]
+    check_config: bool,
+    /// Mailbox to operate on
+    #[arg(long)]
+    mailbox: Option<String>,
+    /// Write the local copy of the mailbox to an mbox file, without connecting to the server
+    #[arg(long, requires = "mailbox")]
+    export: Option<PathBuf>,
+    /// Upload the mail of an mbox file to the mailbox, skipping mail already stored locally
+    #[arg(long, requires = "mailbox", conflicts_with = "export")]
+    import: Option<PathBuf>,
+    /// Print the conversations of the mailbox as threaded by the server
+    #[arg(long, requires = "mailbox")]
+    threads: bool,
+    /// Print the flags of each mail of the mailbox as they are written to the maildir
+    #[arg(long, requires = "mailbox")]
+    flags: bool,
+    /// List the parts of a mail, numbered as --fetch-part expects them
+    #[arg(long, requires = "mailbox", value_name = "UID")]
+    parts: Option<u32>,
+    /// Store a single part of a mail, e.g. 7:2.1 for part 2.1 of uid 7, in the maildir's parts/
+    #[arg(long, requires = "mailbox", value_name = "UID:PART", value_parser = parse_part)]
+    fetch_part: Option<(u32, Vec<u32>)>,
+}
+
+fn parse_part(spec: &str) -> Result<(u32, Vec<u32>), String> {
+    let (uid, part) = spec
+        .split_once(':')
+        .ok_or("should be <uid>:<part>, e.g. 7:2.1")?;
+    let uid = uid.parse().map_err(|_| format!("invalid uid {uid}"))?;
+    let part = part
+        .split('.')
+        .map(|number| number.parse().map_err(|_| format!("invalid part {part}")))
+        .collect::<Result<_, _>>()?;
+    Ok((uid, part))
+}
 
 #[tokio::main]
-async fn main() {
-    let config = Config::load_from_file();
-    Client::new(&config).await;
+async fn main() -> ExitCode {
+    let args = Args::parse();
+    let config = match Config::load_from_file(args.config.as_deref()) {
+        Ok(config) => config,
+        Err(problem) if args.check_config => {
+            println!("\x1b[31m✗\x1b[0m config {problem}");
+            return ExitCode::FAILURE;
+        }
+        Err(problem) => {
+            eprintln!("config {problem}");
+            return ExitCode::FAILURE;
+        }
+    };
+    if args.check_config {
+        return check_config(&config);
+    }
+    let mut valid = true;
+    // the same checks as --check-config, so that a config passing it doesn't fail later on
+    for (checked, result) in config.check() {
+        if let Err(problem) = result {
+            eprintln!("{checked}: {problem}");
+            valid = false;
+        }
+    }
+    if !valid {
+        return ExitCode::FAILURE;
+    }
+    config.create_dirs();
+    if let (Some(mailbox), Some(out)) = (&args.mailbox, &args.export) {
+        // the server's hierarchy delimiter is unknown offline, '/' is the most common one
+        let maildir = config.mailbox_dir(mailbox, '/');
+        return match export_mbox(&maildir, out) {
+            Ok(count) => {
+                println!("exported {count} messages to {}", out.display());
+                ExitCode::SUCCESS
+            }
+            Err(error) => {
+                eprintln!("exporting {} failed: {error}", maildir.display());
+                ExitCode::FAILURE
+            }
+        };
+    }
+    let password = match config.password() {
+        Ok(password) => password,
+        Err(problem) => {
+            eprintln!("{problem}");
+            return ExitCode::FAILURE;
+        }
+    };
+    let mut client = Client::new(&config).await;
+    if let Err(error) = client.login(config.user(), &password).await {
+        eprintln!("{error}");
+        client.logout().await;
+        return ExitCode::FAILURE;
+    }
+    let mut exit_code = ExitCode::SUCCESS;
+    if let (Some(mailbox), Some(mbox)) = (&args.mailbox, &args.import) {
+        let maildir = config.mailbox_dir(mailbox, '/');
+        if config.read_only(mailbox) {
+            eprintln!("{mailbox} only mirrors the server, so nothing is imported into it");
+            exit_code = ExitCode::FAILURE;
+        } else {
+            match import_mbox(&mut client, mailbox, mbox, &maildir).await {
+                Ok(count) => println!("imported {count} messages from {}", mbox.display()),
+                Err(error) => {
+                    eprintln!("importing {} failed: {error}", mbox.display());
+                    exit_code = ExitCode::FAILURE;
+                }
+            }
+        }
+    }
+    if let (Some(mailbox), true) = (&args.mailbox, args.threads) {
+        let threads = match client.select(mailbox, config.read_only(mailbox)).await {
+            Ok(()) => client
+                .thread(ThreadAlgorithm::References)
+                .await
+                .map_err(|error| error.to_string()),
+            Err(error) => Err(error.to_string()),
+        };
+        match threads {
+            Ok(threads) => print_threads(&threads, 0),
+            Err(error) => {
+                eprintln!("{error}");
+                exit_code = ExitCode::FAILURE;
+            }
+        }
+    }
+    if let (Some(mailbox), true) = (&args.mailbox, args.flags) {
+        let keyword_letter = |keyword: &str| config.keyword_letter(mailbox, keyword);
+        let ignores_flag = |flag: &str| config.ignores_flag(mailbox, flag);
+        let flags = match client.select(mailbox, config.read_only(mailbox)).await {
+            Ok(()) => {
+                let uids = client
+                    .uid_next()
+                    .map_or_else(SequenceSet::all, SequenceSet::below);
+                client
+                    .fetch_flags(&uids, keyword_letter, ignores_flag)
+                    .await
+                    .map_err(|error| error.to_string())
+            }
+            Err(error) => Err(error.to_string()),
+        };
+        match flags {
+            Ok(flags) => {
+                for (uid, letters) in flags {
+                    println!("{uid}\t{letters}");
+                }
+            }
+            Err(error) => {
+                eprintln!("{error}");
+                exit_code = ExitCode::FAILURE;
+            }
+        }
+    }
+    if let (Some(mailbox), Some(uid)) = (&args.mailbox, args.parts) {
+        let structures = match client.select(mailbox, config.read_only(mailbox)).await {
+            Ok(()) => client
+                .fetch_structure(&SequenceSet::from_numbers([uid]))
+                .await
+                .map_err(|error| error.to_string()),
+            Err(error) => Err(error.to_string()),
+        };
+        match structures {
+            Ok(structures) if structures.is_empty() => {
+                eprintln!("no such mail: {uid}");
+                exit_code = ExitCode::FAILURE;
+            }
+            Ok(structures) => structures
+                .iter()
+                .flat_map(|structure| &structure.parts)
+                .for_each(print_part),
+            Err(error) => {
+                eprintln!("{error}");
+                exit_code = ExitCode::FAILURE;
+            }
+        }
+    }
+    if let (Some(mailbox), Some((uid, part))) = (&args.mailbox, &args.fetch_part) {
+        let maildir = config.mailbox_dir(mailbox, '/');
+        let downloaded = match client.select(mailbox, config.read_only(mailbox)).await {
+            Ok(()) => download_part(&mut client, *uid, part, &maildir, config.file_mode())
+                .await
+                .map_err(|error| error.to_string()),
+            Err(error) => Err(error.to_string()),
+        };
+        match downloaded {
+            Ok(path) => println!("stored part in {}", path.display()),
+            Err(error) => {
+                eprintln!("{error}");
+                exit_code = ExitCode::FAILURE;
+            }
+        }
+    }
+    client.logout().await;
+    exit_code
+}
+
+fn print_threads(threads: &[Thread], depth: usize) {
+    for thread in threads {
+        match thread.uid {
+            Some(uid) => println!("{}{uid}", "  ".repeat(depth)),
+            None => println!("{}(missing)", "  ".repeat(depth)),
+        }
+        print_threads(&thread.children, depth + 1);
+    }
+}
+
+fn print_part(part: &MessagePart) {
+    let number: Vec<String> = part.part.iter().map(u32::to_string).collect();
+    println!(
+        "{}\t{}/{}\t{} octets\t{}",
+        number.join("."),
+        part.media_type,
+        part.media_subtype,
+        part.octets,
+        part.filename.as_deref().unwrap_or_default()
+    );
+}
+
+fn check_config(config: &Config) -> ExitCode {
+    let mut exit_code = ExitCode::SUCCESS;
+    for (checked, result) in config.check() {
+        match result {
+            Ok(()) => println!("\x1b[32m✓\x1b[0m {checked}"),
+            Err(problem) => {
+                println!("\x1b[31m✗\x1b[0m {checked}: {problem}");
+                exit_code = ExitCode::FAILURE;
+            }
+        }
+    }
+    exit_code
 }
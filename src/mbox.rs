@@ -0,0 +1,363 @@
+use std::{
+    collections::HashSet,
+    fs::{self, File},
+    io::{self, BufRead, BufReader, BufWriter, Write},
+    path::Path,
+    time::SystemTime,
+};
+
+use chrono::{DateTime, FixedOffset, NaiveDateTime, Utc};
+
+use crate::client::Client;
+
+// writes all mail of a maildir into a single mboxrd file, https://www.loc.gov/preservation/digital/formats/fdd/fdd000385.shtml
+// uids aren't kept locally yet, so mail is ordered by file name, which starts with the delivery time
+pub fn export(maildir: &Path, out: &Path) -> io::Result<usize> {
+    let mut paths = Vec::new();
+    for subdir in ["cur", "new"] {
+        for entry in fs::read_dir(maildir.join(subdir))? {
+            let entry = entry?;
+            if entry.file_type()?.is_file() {
+                paths.push(entry.path());
+            }
+        }
+    }
+    paths.sort_by(|a, b| a.file_name().cmp(&b.file_name()));
+
+    let mut writer = BufWriter::new(File::create(out)?);
+    for path in &paths {
+        let message = fs::read(path)?;
+        let received = fs::metadata(path)?.modified()?;
+        write_message(&mut writer, &message, received)?;
+    }
+    writer.flush()?;
+    Ok(paths.len())
+}
+
+fn write_message(out: &mut impl Write, message: &[u8], received: SystemTime) -> io::Result<()> {
+    let date = header_date(message).unwrap_or_else(|| received.into());
+    writeln!(
+        out,
+        "From MAILER-DAEMON {}",
+        date.format("%a %b %e %H:%M:%S %Y")
+    )?;
+    for line in message.split_inclusive(|&byte| byte == b'\n') {
+        if is_from_line(line) {
+            out.write_all(b">")?;
+        }
+        out.write_all(line)?;
+    }
+    if !message.ends_with(b"\n") {
+        out.write_all(b"\n")?;
+    }
+    // an empty line separates messages
+    out.write_all(b"\n")
+}
+
+// mboxrd quotes every line starting with any number of '>' followed by "From ", so that reading
+// can strip exactly one '>' again
+fn is_from_line(line: &[u8]) -> bool {
+    let unquoted = line
+        .iter()
+        .position(|&byte| byte != b'>')
+        .unwrap_or(line.len());
+    line[unquoted..].starts_with(b"From ")
+}
+
+fn header_date(message: &[u8]) -> Option<DateTime<Utc>> {
+    DateTime::parse_from_rfc2822(&header(message, "Date")?)
+        .ok()
+        .map(|date| date.with_timezone(&Utc))
+}
+
+// the unfolded value of the first header field with the given name
+fn header(message: &[u8], name: &str) -> Option<String> {
+    let message = String::from_utf8_lossy(message);
+    let mut lines = message
+        .lines()
+        .take_while(|line| !line.is_empty())
+        .skip_while(|line| {
+            !line
+                .split_once(':')
+                .is_some_and(|(field, _)| field.eq_ignore_ascii_case(name))
+        });
+    let mut value = lines.next()?[name.len() + 1..].to_owned();
+    // folded header lines continue with whitespace
+    for continuation in lines.take_while(|line| line.starts_with([' ', '\t'])) {
+        value.push_str(continuation);
+    }
+    Some(value.trim().to_owned())
+}
+
+// appends all mail of an mbox file that isn't in the local maildir yet, as told by its Message-ID
+// the imported mail is downloaded again by the next sync like any other new mail
+pub async fn import(
+    client: &mut Client,
+    mailbox: &str,
+    mbox: &Path,
+    maildir: &Path,
+) -> io::Result<usize> {
+    let mut known = message_ids(maildir)?;
+    let mut imported = 0;
+    for message in Messages::new(BufReader::new(File::open(mbox)?)) {
+        let message = message?;
+        if let Some(message_id) = header(&message.content, "Message-ID") {
+            if !known.insert(message_id) {
+                continue;
+            }
+        }
+        client
+            .append(
+                mailbox,
+                &[],
+                message.received.as_ref(),
+                &crlf_line_endings(&message.content),
+            )
+            .await
+            .map_err(|error| io::Error::other(error.to_string()))?;
+        imported += 1;
+    }
+    Ok(imported)
+}
+
+// a mailbox that was never synced has no maildir yet, so nothing is known about it
+fn message_ids(maildir: &Path) -> io::Result<HashSet<String>> {
+    let mut message_ids = HashSet::new();
+    for subdir in ["cur", "new"] {
+        let entries = match fs::read_dir(maildir.join(subdir)) {
+            Ok(entries) => entries,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => continue,
+            Err(error) => return Err(error),
+        };
+        for entry in entries {
+            let entry = entry?;
+            if !entry.file_type()?.is_file() {
+                continue;
+            }
+            // only the header is needed, the body may be large
+            // it ends at the first empty line, which is "\r\n" in mail downloaded from the server
+            let mut head = Vec::new();
+            let mut reader = BufReader::new(File::open(entry.path())?);
+            loop {
+                let mut line = Vec::new();
+                if reader.read_until(b'\n', &mut line)? == 0 || line == b"\n" || line == b"\r\n" {
+                    break;
+                }
+                head.extend_from_slice(&line);
+            }
+            if let Some(message_id) = header(&head, "Message-ID") {
+                message_ids.insert(message_id);
+            }
+        }
+    }
+    Ok(message_ids)
+}
+
+// IMAP requires CRLF line endings, while mbox files use the local LF
+fn crlf_line_endings(message: &[u8]) -> Vec<u8> {
+    let mut converted = Vec::with_capacity(message.len());
+    for line in message.split_inclusive(|&byte| byte == b'\n') {
+        match line.strip_suffix(b"\n") {
+            Some(line) => {
+                converted.extend_from_slice(line.strip_suffix(b"\r").unwrap_or(line));
+                converted.extend_from_slice(b"\r\n");
+            }
+            None => converted.extend_from_slice(line),
+        }
+    }
+    converted
+}
+
+pub struct Message {
+    // from the "From " line, if it carries a date
+    pub received: Option<DateTime<FixedOffset>>,
+    pub content: Vec<u8>,
+}
+
+// reads an mboxrd file message by message, so that large files need not fit in memory
+pub struct Messages<R> {
+    reader: R,
+    // the "From " line starting the next message, already consumed while reading the previous one
+    from_line: Option<Vec<u8>>,
+}
+
+impl<R: BufRead> Messages<R> {
+    pub fn new(reader: R) -> Self {
+        Self {
+            reader,
+            from_line: None,
+        }
+    }
+}
+
+impl<R: BufRead> Iterator for Messages<R> {
+    type Item = io::Result<Message>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut from_line = self.from_line.take();
+        let mut content = Vec::new();
+        loop {
+            let mut line = Vec::new();
+            match self.reader.read_until(b'\n', &mut line) {
+                Ok(0) => break,
+                Ok(_) => {}
+                Err(error) => return Some(Err(error)),
+            }
+            if line.starts_with(b"From ") {
+                if from_line.is_some() {
+                    self.from_line = Some(line);
+                    break;
+                }
+                from_line = Some(line);
+            } else if from_line.is_some() {
+                if is_from_line(&line) {
+                    line.remove(0);
+                }
+                content.extend_from_slice(&line);
+            }
+            // anything before the first "From " line is not part of a message
+        }
+        let from_line = from_line?;
+        // the empty line separating messages
+        if content.ends_with(b"\n\n") {
+            content.pop();
+        }
+        Some(Ok(Message {
+            received: received(&from_line),
+            content,
+        }))
+    }
+}
+
+// "From sender Tue Feb  8 05:52:25 1994", in UTC
+fn received(from_line: &[u8]) -> Option<DateTime<FixedOffset>> {
+    let from_line = String::from_utf8_lossy(from_line);
+    let (_, date) = from_line
+        .trim_end()
+        .strip_prefix("From ")?
+        .split_once(' ')?;
+    NaiveDateTime::parse_from_str(date.trim(), "%a %b %e %H:%M:%S %Y")
+        .ok()
+        .map(|date| date.and_utc().fixed_offset())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quote_from_lines() {
+        let message = b"Date: Mon, 7 Feb 1994 21:52:25 -0800\nSubject: test\n\nFrom here\n>From there\nnot From\n";
+        let mut out = Vec::new();
+        write_message(&mut out, message, SystemTime::UNIX_EPOCH).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "From MAILER-DAEMON Tue Feb  8 05:52:25 1994\n\
+             Date: Mon, 7 Feb 1994 21:52:25 -0800\n\
+             Subject: test\n\
+             \n\
+             >From here\n\
+             >>From there\n\
+             not From\n\
+             \n"
+        );
+    }
+
+    #[test]
+    fn fall_back_to_received_time() {
+        let mut out = Vec::new();
+        write_message(&mut out, b"Date: yesterday\n\nbody", SystemTime::UNIX_EPOCH).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "From MAILER-DAEMON Thu Jan  1 00:00:00 1970\nDate: yesterday\n\nbody\n\n"
+        );
+    }
+
+    #[test]
+    fn read_messages_written_by_export() {
+        let mut mbox = Vec::new();
+        write_message(
+            &mut mbox,
+            b"Date: Mon, 7 Feb 1994 21:52:25 -0800\n\nFrom here\n>From there\n",
+            SystemTime::UNIX_EPOCH,
+        )
+        .unwrap();
+        write_message(
+            &mut mbox,
+            b"Subject: second\n\nbody",
+            SystemTime::UNIX_EPOCH,
+        )
+        .unwrap();
+
+        let messages: Vec<_> = Messages::new(&mbox[..]).map(Result::unwrap).collect();
+        assert_eq!(messages.len(), 2);
+        assert_eq!(
+            messages[0].received,
+            DateTime::parse_from_rfc2822("Tue, 8 Feb 1994 05:52:25 +0000").ok()
+        );
+        assert_eq!(
+            messages[0].content,
+            b"Date: Mon, 7 Feb 1994 21:52:25 -0800\n\nFrom here\n>From there\n"
+        );
+        assert_eq!(messages[1].content, b"Subject: second\n\nbody\n");
+    }
+
+    #[test]
+    fn look_up_folded_header() {
+        let message =
+            b"Subject: test\nmessage-id:\n <1@example.org>\n\nMessage-ID: <2@example.org>\n";
+        assert_eq!(
+            header(message, "Message-ID"),
+            Some("<1@example.org>".to_string())
+        );
+        assert_eq!(header(message, "Date"), None);
+        assert_eq!(crlf_line_endings(b"a\nb\r\nc"), b"a\r\nb\r\nc");
+    }
+
+    #[test]
+    fn export_in_delivery_order() {
+        let maildir =
+            std::env::temp_dir().join(format!("imapmaildir-export-{}", std::process::id()));
+        for subdir in ["cur", "new", "tmp"] {
+            fs::create_dir_all(maildir.join(subdir)).unwrap();
+        }
+        fs::write(maildir.join("new/1700000002.P1N0.host"), "Subject: new\n\n").unwrap();
+        fs::write(
+            maildir.join("cur/1700000001.P1N0.host:2,S"),
+            "Subject: old\n\n",
+        )
+        .unwrap();
+        fs::write(
+            maildir.join("tmp/1700000003.P1N0.host"),
+            "Subject: partial\n\n",
+        )
+        .unwrap();
+        let out = maildir.join("out.mbox");
+
+        assert_eq!(export(&maildir, &out).unwrap(), 2);
+        let mbox = fs::read_to_string(&out).unwrap();
+        fs::remove_dir_all(&maildir).unwrap();
+        let subjects: Vec<_> = mbox
+            .lines()
+            .filter(|line| line.starts_with("Subject:"))
+            .collect();
+        assert_eq!(subjects, ["Subject: old", "Subject: new"]);
+    }
+
+    #[test]
+    fn collect_message_ids_from_header_only() {
+        let maildir =
+            std::env::temp_dir().join(format!("imapmaildir-message-ids-{}", std::process::id()));
+        assert_eq!(message_ids(&maildir).unwrap(), HashSet::new());
+
+        fs::create_dir_all(maildir.join("cur")).unwrap();
+        fs::write(
+            maildir.join("cur/1700000001.P1N0.host:2,S"),
+            "Message-ID: <1@example.org>\r\n\r\nMessage-ID: <2@example.org>\r\n",
+        )
+        .unwrap();
+        let message_ids = message_ids(&maildir).unwrap();
+        fs::remove_dir_all(&maildir).unwrap();
+        assert_eq!(message_ids, HashSet::from(["<1@example.org>".to_string()]));
+    }
+}
@@ -0,0 +1,66 @@
+use std::{io, path::Path, path::PathBuf};
+
+use crate::{
+    client::Client,
+    maildir::{part_path, write_part},
+};
+
+// downloads a single part of mail in the selected mailbox next to the maildir, e.g. an attachment
+// of mail that is kept without it
+pub async fn download(
+    client: &mut Client,
+    uid: u32,
+    part: &[u32],
+    maildir: &Path,
+    mode: u32,
+) -> io::Result<PathBuf> {
+    let content = client
+        .fetch_part(uid, part, None)
+        .await
+        .map_err(|error| io::Error::other(error.to_string()))?;
+    let path = part_path(maildir, uid, part);
+    write_part(&path, None, &content, mode)?;
+    Ok(path)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+
+    use super::*;
+    use crate::{
+        client::scripted_server::{serve, Step, DOVECOT_GREETING},
+        config::Timeouts,
+    };
+
+    #[tokio::test]
+    async fn write_fetched_part_to_sidecar_file() {
+        let (transport, server) = serve(vec![
+            Step {
+                command: None,
+                responses: DOVECOT_GREETING.as_bytes(),
+            },
+            Step {
+                command: Some("a0001 CAPABILITY"),
+                responses: b"a0001 OK Capability completed.\r\n",
+            },
+            Step {
+                command: Some("a0002 UID FETCH 7 BODY.PEEK[2.1]"),
+                responses:
+                    b"* 1 FETCH (UID 7 BODY[2.1] {4}\r\n\x89PNG)\r\na0002 OK Fetch completed.\r\n",
+            },
+        ]);
+        let mut client = Client::with_transport(transport, Timeouts::default()).await;
+        let maildir =
+            std::env::temp_dir().join(format!("imapmaildir-download-{}", std::process::id()));
+
+        let path = download(&mut client, 7, &[2, 1], &maildir, 0o600)
+            .await
+            .expect("part should be downloadable");
+        let content = fs::read(&path).expect("part should be readable");
+        fs::remove_dir_all(&maildir).expect("maildir should be removable");
+        assert_eq!(path, maildir.join("parts/7.2.1"));
+        assert_eq!(content, b"\x89PNG");
+        server.await.expect("client should follow the script");
+    }
+}